@@ -0,0 +1,146 @@
+use crate::{utils::Interval, Ray, Vec3};
+
+///An axis-aligned bounding box, stored as one interval per axis.
+#[derive(Copy, Clone)]
+pub struct Aabb{
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval
+}
+impl Aabb{
+    ///Returns the box spanned by two opposite corners, in any order.
+    pub fn from_points(a: Vec3, b: Vec3) -> Aabb{
+        Aabb{
+            x: Interval::new(f64::min(a.x, b.x), f64::max(a.x, b.x)),
+            y: Interval::new(f64::min(a.y, b.y), f64::max(a.y, b.y)),
+            z: Interval::new(f64::min(a.z, b.z), f64::max(a.z, b.z))
+        }
+    }
+    ///Returns a copy of the box widened along any axis thinner than a small delta,
+    ///so flat primitives such as axis-aligned triangles can still be hit.
+    pub fn padded(&self) -> Aabb{
+        let delta = 1e-4;
+        let pad = |slab: &Interval| if slab.size() < delta{
+            Interval::new(slab.min-delta/2.0, slab.max+delta/2.0)
+        }else{
+            *slab
+        };
+        Aabb{x: pad(&self.x), y: pad(&self.y), z: pad(&self.z)}
+    }
+    ///Returns the smallest box enclosing both boxes.
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb{
+        Aabb{
+            x: Interval::enclosing(&a.x, &b.x),
+            y: Interval::enclosing(&a.y, &b.y),
+            z: Interval::enclosing(&a.z, &b.z)
+        }
+    }
+    pub fn axis(&self, n: usize) -> &Interval{
+        match n{
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z
+        }
+    }
+    ///Returns the index of the axis along which the box is largest.
+    pub fn longest_axis(&self) -> usize{
+        if self.x.size() > self.y.size(){
+            if self.x.size() > self.z.size() {0} else {2}
+        }else if self.y.size() > self.z.size() {1} else {2}
+    }
+    pub fn centroid(&self) -> Vec3{
+        Vec3::new(
+            (self.x.min+self.x.max)/2.0,
+            (self.y.min+self.y.max)/2.0,
+            (self.z.min+self.z.max)/2.0
+        )
+    }
+    ///Returns false for boxes that extend infinitely, such as those of planes.
+    pub fn is_bounded(&self) -> bool{
+        self.x.size().is_finite() && self.y.size().is_finite() && self.z.size().is_finite()
+    }
+    pub fn surface_area(&self) -> f64{
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0*(dx*dy + dy*dz + dz*dx)
+    }
+    ///Returns true if the ray passes through the box within ray_t.
+    pub fn hit(&self, r: &Ray, ray_t: &Interval) -> bool{
+        self.clip(r, ray_t).is_some()
+    }
+    ///Returns the part of ray_t for which the ray is inside the box, using the slab method.
+    pub fn clip(&self, r: &Ray, ray_t: &Interval) -> Option<Interval>{
+        let origin = [r.origin.x, r.origin.y, r.origin.z];
+        let direction = [r.direction.x, r.direction.y, r.direction.z];
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+
+        for axis in 0..3{
+            let slab = self.axis(axis);
+            let inverse = 1.0/direction[axis];
+            let t0 = (slab.min-origin[axis])*inverse;
+            let t1 = (slab.max-origin[axis])*inverse;
+            let (near, far) = if t0 < t1 {(t0, t1)} else {(t1, t0)};
+
+            t_min = f64::max(t_min, near);
+            t_max = f64::min(t_max, far);
+            if t_max <= t_min{
+                return None;
+            }
+        }
+        Some(Interval::new(t_min, t_max))
+    }
+}
+pub const EMPTY_BOX: Aabb = Aabb{x: crate::utils::EMPTY, y: crate::utils::EMPTY, z: crate::utils::EMPTY};
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn unit_box() -> Aabb{
+        Aabb::from_points(Vec3::new(1.0, 1.0, 1.0), Vec3::new(-1.0, -1.0, -1.0))
+    }
+
+    #[test]
+    fn clip_returns_entry_and_exit(){
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let span = unit_box().clip(&r, &Interval::new(0.0, f64::INFINITY)).unwrap();
+        assert!((span.min-2.0).abs() < 1e-12 && (span.max-3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn clip_starts_inside(){
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let span = unit_box().clip(&r, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(span.min, 0.001);
+        assert!((span.max-1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn misses(){
+        let r = Ray::new(Vec3::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(!unit_box().hit(&r, &Interval::new(0.0, f64::INFINITY)));
+        //Pointing away from the box.
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(!unit_box().hit(&r, &Interval::new(0.0, f64::INFINITY)));
+        //Beyond the end of ray_t.
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(!unit_box().hit(&r, &Interval::new(0.0, 3.0)));
+    }
+
+    #[test]
+    fn axis_parallel_rays(){
+        //Zero direction components divide to infinities, which must still give the right answer.
+        let inside = Ray::new(Vec3::new(0.5, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let outside = Ray::new(Vec3::new(1.5, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(unit_box().hit(&inside, &Interval::new(0.0, f64::INFINITY)));
+        assert!(!unit_box().hit(&outside, &Interval::new(0.0, f64::INFINITY)));
+    }
+
+    #[test]
+    fn padded_flat_box(){
+        let flat = Aabb::from_points(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0)).padded();
+        assert!(flat.y.size() > 0.0);
+        let r = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(flat.hit(&r, &Interval::new(0.0, f64::INFINITY)));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{environment::EnvironmentMap, sampler::Sampler, sky::Sky, vec3::unit_vector, Ray, Vec3};
+
+///The radiance seen along rays that leave the scene without hitting anything.
+pub enum Background{
+    ///The same color in every direction; black makes a scene lit only by its emitters.
+    Solid(Vec3),
+    ///A vertical blend from the horizon color (looking down) to the zenith color (looking up).
+    Gradient{horizon: Vec3, zenith: Vec3},
+    ///An HDR image surrounding the scene, which is also sampled directly as a light.
+    Environment(EnvironmentMap),
+    ///A physically based daylight sky with the sun's disk, which is also sampled directly as a light.
+    Sky(Sky)
+}
+impl Background{
+    pub fn color(&self, r: &Ray) -> Vec3{
+        match self{
+            Background::Solid(color) => *color,
+            Background::Gradient{horizon, zenith} => {
+                let unit_direction = unit_vector(&r.direction);
+                let a = unit_direction.y/2.0+0.5;
+                *horizon*(1.0-a)+*zenith*a
+            }
+            Background::Environment(map) => map.color(&r.direction),
+            Background::Sky(sky) => sky.color(&r.direction)
+        }
+    }
+    ///Returns true if the background can be sampled as a light with sample and pdf.
+    pub fn is_sampled(&self) -> bool{
+        match self{
+            Background::Environment(map) => !map.is_black(),
+            Background::Sky(_) => true,
+            _ => false
+        }
+    }
+    ///Returns the density over solid angle with which sample picks direction.
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        match self{
+            Background::Environment(map) if !map.is_black() => map.pdf(direction),
+            Background::Sky(sky) => sky.pdf(direction),
+            _ => 0.0
+        }
+    }
+    ///Returns a random direction toward the background. Only valid when is_sampled is true.
+    pub fn sample(&self, rng: &mut Sampler) -> Vec3{
+        match self{
+            Background::Environment(map) => map.sample(rng),
+            Background::Sky(sky) => sky.sample(rng),
+            _ => Vec3::new(0.0, 1.0, 0.0)
+        }
+    }
+}
+impl Default for Background{
+    ///The white-to-blue sky from the book.
+    fn default() -> Background{
+        Background::Gradient{horizon: Vec3::new(1.0, 1.0, 1.0), zenith: Vec3::new(0.5, 0.7, 1.0)}
+    }
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::{aabb::{Aabb, EMPTY_BOX}, hittable::HitRecord, sampler::Sampler, utils::Interval, Hittable, Ray, Vec3};
+
+///Number of buckets centroids are binned into when evaluating split candidates.
+const SAH_BUCKETS: usize = 12;
+///Maximum number of objects stored in a single leaf.
+const MAX_LEAF_SIZE: usize = 4;
+
+///A bounding volume hierarchy over a set of objects, split using the surface area heuristic.
+pub struct BvhNode{
+    bbox: Aabb,
+    contents: BvhContents
+}
+enum BvhContents{
+    Leaf(Vec<Box<dyn Hittable>>),
+    Branch(Box<BvhNode>, Box<BvhNode>)
+}
+impl BvhNode{
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> BvhNode{
+        let bbox = objects.iter().fold(EMPTY_BOX, |bbox, o| Aabb::surrounding(&bbox, &o.bounding_box()));
+        if objects.len() <= MAX_LEAF_SIZE{
+            return BvhNode{bbox, contents: BvhContents::Leaf(objects)};
+        }
+
+        let centroid_bounds = objects.iter().fold(EMPTY_BOX, |bounds, o| {
+            let c = o.bounding_box().centroid();
+            Aabb::surrounding(&bounds, &Aabb::from_points(c, c))
+        });
+        let axis = centroid_bounds.longest_axis();
+        let extent = *centroid_bounds.axis(axis);
+        if extent.size() <= 0.0{
+            return BvhNode::split_at(objects, axis, bbox);
+        }
+
+        let bucket_of = |o: &dyn Hittable| -> usize{
+            let c = centroid_component(&o.bounding_box(), axis);
+            let b = ((c-extent.min)/extent.size()*SAH_BUCKETS as f64) as usize;
+            usize::min(b, SAH_BUCKETS-1)
+        };
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bounds = [EMPTY_BOX; SAH_BUCKETS];
+        for o in objects.iter(){
+            let b = bucket_of(o.as_ref());
+            counts[b] += 1;
+            bounds[b] = Aabb::surrounding(&bounds[b], &o.bounding_box());
+        }
+
+        //Cost of splitting after each bucket, relative to the parent's surface area.
+        let mut best_split = 0;
+        let mut best_cost = f64::MAX;
+        for split in 0..SAH_BUCKETS-1{
+            let (mut left_box, mut left_count) = (EMPTY_BOX, 0);
+            let (mut right_box, mut right_count) = (EMPTY_BOX, 0);
+            for b in 0..=split{
+                left_box = Aabb::surrounding(&left_box, &bounds[b]);
+                left_count += counts[b];
+            }
+            for b in split+1..SAH_BUCKETS{
+                right_box = Aabb::surrounding(&right_box, &bounds[b]);
+                right_count += counts[b];
+            }
+            if left_count == 0 || right_count == 0{
+                continue;
+            }
+            let cost = left_count as f64*left_box.surface_area() + right_count as f64*right_box.surface_area();
+            if cost < best_cost{
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        if best_cost == f64::MAX{
+            return BvhNode::split_at(objects, axis, bbox);
+        }
+        let (left, right): (Vec<_>, Vec<_>) = objects.into_iter().partition(|o| bucket_of(o.as_ref()) <= best_split);
+        BvhNode{
+            bbox,
+            contents: BvhContents::Branch(Box::new(BvhNode::new(left)), Box::new(BvhNode::new(right)))
+        }
+    }
+    ///Falls back to a median split along the axis when the buckets cannot separate the objects.
+    fn split_at(mut objects: Vec<Box<dyn Hittable>>, axis: usize, bbox: Aabb) -> BvhNode{
+        objects.sort_by(|a, b| {
+            centroid_component(&a.bounding_box(), axis).total_cmp(&centroid_component(&b.bounding_box(), axis))
+        });
+        let right = objects.split_off(objects.len()/2);
+        BvhNode{
+            bbox,
+            contents: BvhContents::Branch(Box::new(BvhNode::new(objects)), Box::new(BvhNode::new(right)))
+        }
+    }
+}
+fn centroid_component(bbox: &Aabb, axis: usize) -> f64{
+    let slab = bbox.axis(axis);
+    (slab.min+slab.max)/2.0
+}
+impl Hittable for BvhNode{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        if !self.bbox.hit(r, ray_t){
+            return None;
+        }
+        match &self.contents{
+            BvhContents::Leaf(objects) => {
+                let mut hit_record: Option<HitRecord> = None;
+                let mut nearest = ray_t.max;
+                for o in objects.iter(){
+                    if let Some(record) = o.intersect(r, &Interval::new(ray_t.min, nearest)){
+                        nearest = record.t;
+                        hit_record = Some(record);
+                    }
+                }
+                hit_record
+            }
+            BvhContents::Branch(left, right) => {
+                let hit_left = left.intersect(r, ray_t);
+                let nearest = hit_left.as_ref().map_or(ray_t.max, |record| record.t);
+                let hit_right = right.intersect(r, &Interval::new(ray_t.min, nearest));
+                hit_right.or(hit_left)
+            }
+        }
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        if !self.bbox.hit(r, ray_t){
+            return 1.0;
+        }
+        match &self.contents{
+            BvhContents::Leaf(objects) => {
+                let mut transmittance = 1.0;
+                for o in objects.iter(){
+                    transmittance *= o.transmittance(r, ray_t);
+                    if transmittance == 0.0{
+                        break;
+                    }
+                }
+                transmittance
+            }
+            BvhContents::Branch(left, right) => {
+                let left = left.transmittance(r, ray_t);
+                if left == 0.0 {0.0} else {left*right.transmittance(r, ray_t)}
+            }
+        }
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+}
+
+///A bounding volume hierarchy over a named group of objects that also samples the emitters among them,
+///each equally often as the scene does, so that lights placed by instances are sampled directly.
+pub struct Group{
+    bvh: BvhNode,
+    emitters: Vec<Arc<dyn Hittable>>
+}
+impl Group{
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> Group{
+        let mut emitters = Vec::new();
+        let objects = objects.into_iter().map(|object| {
+            if !object.is_emitter(){
+                return object;
+            }
+            let emitter: Arc<dyn Hittable> = Arc::from(object);
+            emitters.push(emitter.clone());
+            Box::new(emitter) as Box<dyn Hittable>
+        }).collect();
+        Group{bvh: BvhNode::new(objects), emitters}
+    }
+}
+impl Hittable for Group{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        self.bvh.intersect(r, ray_t)
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bvh.bounding_box()
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        self.bvh.transmittance(r, ray_t)
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        let total: f64 = self.emitters.iter().map(|emitter| emitter.pdf_value(origin, direction)).sum();
+        total/self.emitters.len() as f64
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        self.emitters[rng.gen_range(0..self.emitters.len())].random(origin, rng)
+    }
+    fn is_emitter(&self) -> bool{
+        !self.emitters.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use rand::SeedableRng;
+    use crate::{hittable::Sphere, material::Lambertian, sampler::Sampler, vec3::random_unit_vector, Vec3};
+    use super::*;
+
+    fn spheres(rng: &mut Sampler) -> Vec<Sphere>{
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        (0..200).map(|_| {
+            let center = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+            Sphere::new(center, rng.gen_range(0.1..1.0), material.clone())
+        }).collect()
+    }
+
+    #[test]
+    fn finds_the_nearest_hit(){
+        let mut rng = Sampler::seed_from_u64(1);
+        let objects: Vec<Box<dyn Hittable>> = spheres(&mut rng).into_iter().map(|s| Box::new(s) as Box<dyn Hittable>).collect();
+        let brute_force = spheres(&mut Sampler::seed_from_u64(1));
+        let bvh = BvhNode::new(objects);
+
+        let mut hits = 0;
+        for _ in 0..2000{
+            let origin = Vec3::new(rng.gen_range(-12.0..12.0), rng.gen_range(-12.0..12.0), rng.gen_range(-12.0..12.0));
+            let r = Ray::new(origin, random_unit_vector(&mut rng), 0.0);
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            let expected = brute_force.iter()
+                .filter_map(|s| s.intersect(&r, &ray_t))
+                .map(|record| record.t)
+                .fold(f64::INFINITY, f64::min);
+            match bvh.intersect(&r, &ray_t){
+                Some(record) => {
+                    assert!((record.t-expected).abs() < 1e-9);
+                    hits += 1;
+                }
+                None => assert_eq!(expected, f64::INFINITY)
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn bounds_every_object(){
+        let mut rng = Sampler::seed_from_u64(2);
+        let objects = spheres(&mut rng);
+        let bbox = objects.iter().fold(EMPTY_BOX, |bbox, o| Aabb::surrounding(&bbox, &o.bounding_box()));
+        let bvh = BvhNode::new(objects.into_iter().map(|s| Box::new(s) as Box<dyn Hittable>).collect());
+        for axis in 0..3{
+            assert_eq!(bvh.bounding_box().axis(axis).min, bbox.axis(axis).min);
+            assert_eq!(bvh.bounding_box().axis(axis).max, bbox.axis(axis).max);
+        }
+    }
+}
@@ -1,15 +1,85 @@
+use std::borrow::Cow;
 use crate::vec3::cross;
+use crate::vec3::dot;
 use crate::vec3::random_in_unit_disk;
 use crate::vec3::Vec3;
-use crate::vec3::random_on_hemisphere;
-use crate::vec3::random_unit_vector;
 use crate::Ray;
 use crate::Scene;
 use crate::Hittable;
 use crate::unit_vector;
 use crate::utils::*;
+use crate::image::Image;
+use crate::sampler::{pixel_sampler, Sampler};
+use crate::material::ScatterKind;
+use crate::hittable::HitRecord;
+use crate::light::Light;
+use crate::pdf::{HittablePdf, Pdf};
 use rand::Rng;
 use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+///Weighs a sample drawn with density a against another strategy with density b, using the power heuristic.
+fn power_heuristic(a: f64, b: f64) -> f64{
+    if a*a+b*b == 0.0 {0.0} else {a*a/(a*a+b*b)}
+}
+
+///Width and height in pixels of the square tiles rendered in parallel.
+const TILE_SIZE: i32 = 16;
+
+///A rectangular region of the image, rendered as one unit of work.
+struct Tile{
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32
+}
+
+///Parameters describing a camera, kept separately from Camera so they can be loaded and overridden before it is built.
+#[derive(Copy, Clone)]
+pub struct CameraSettings{
+    pub aspect_ratio: f64,
+    pub image_width: i32,
+    ///Image height in pixels, overriding the one derived from the width and aspect ratio.
+    pub image_height: Option<i32>,
+    pub vfov: f64,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vup: Vec3,
+    pub defocus_angle: f64,
+    pub focus_dist: f64,
+    ///Times at which the shutter opens and closes. Each ray is sent at a random time between them.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    ///Global seed from which every pixel's random numbers are derived.
+    pub seed: u64
+}
+impl CameraSettings{
+    pub fn build(&self) -> Camera{
+        Camera::new(self)
+    }
+}
+impl Default for CameraSettings{
+    fn default() -> CameraSettings{
+        CameraSettings{
+            aspect_ratio: 16.0/9.0,
+            image_width: 1200,
+            image_height: None,
+            vfov: 20.0,
+            look_from: Vec3::new(13.0, 2.0, 3.0),
+            look_at: Vec3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.1,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            samples_per_pixel: 100,
+            max_depth: 100,
+            seed: 0
+        }
+    }
+}
 
 pub struct Camera{
     image_width: i32,
@@ -22,22 +92,30 @@ pub struct Camera{
     max_depth: i32,
     defocus_angle: f64,
     defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3
+    defocus_disk_v: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
+    seed: u64
 }
 impl Camera{
-    pub fn new(
-        aspect_ratio: f64, 
-        image_width: i32,
-        vfov: f64, 
-        look_from: Vec3,
-        look_at: Vec3,
-        vup: Vec3,
-        defocus_angle: f64,
-        focus_dist: f64,
-        samples_per_pixel: i32,
-        max_depth: i32
-    ) -> Camera{
-        let image_height = (image_width as f64/aspect_ratio) as i32;
+    pub fn new(settings: &CameraSettings) -> Camera{
+        let CameraSettings{
+            aspect_ratio,
+            image_width,
+            image_height,
+            vfov,
+            look_from,
+            look_at,
+            vup,
+            defocus_angle,
+            focus_dist,
+            shutter_open,
+            shutter_close,
+            samples_per_pixel,
+            max_depth,
+            seed
+        } = *settings;
+        let image_height = image_height.unwrap_or((image_width as f64/aspect_ratio) as i32);
 
         let camera_center = look_from;
         let theta = degrees_to_radians(vfov);
@@ -63,77 +141,225 @@ impl Camera{
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Camera{image_width, image_height, camera_center, pixel_delta_u, pixel_delta_v, pixel00_loc, samples_per_pixel, max_depth, defocus_angle, defocus_disk_u, defocus_disk_v}
+        Camera{image_width, image_height, camera_center, pixel_delta_u, pixel_delta_v, pixel00_loc, samples_per_pixel, max_depth, defocus_angle, defocus_disk_u, defocus_disk_v, shutter_open, shutter_close, seed}
     }
 
-    pub fn render(&self, scene: &Scene){
-        std::print!("P3\n{} {}\n255\n", self.image_width, self.image_height);
+    ///Renders the scene into a buffer of linear colors, averaged over all samples per pixel.
+    pub fn render(&self, scene: &Scene) -> Image{
+        let tiles = self.tiles();
+        let progress_bar = ProgressBar::new(tiles.len() as u64);
 
-        let progress_bar = ProgressBar::new(self.image_height as u64);
-        for j in 0..self.image_height{
-            for i in 0..self.image_width{
+        let rendered: Vec<(Tile, Vec<Vec3>)> = tiles
+            .into_par_iter()
+            .map(|tile| {
+                let pixels = self.render_tile(&tile, scene);
+                progress_bar.inc(1);
+                (tile, pixels)
+            })
+            .collect();
+        progress_bar.finish();
+
+        let mut image = Image::new(self.image_width, self.image_height);
+        for (tile, pixels) in rendered{
+            for (index, color) in pixels.into_iter().enumerate(){
+                let i = tile.x + index as i32 % tile.width;
+                let j = tile.y + index as i32 / tile.width;
+                image.set(i, j, color);
+            }
+        }
+        image
+    }
+
+    ///Splits the image into tiles of at most TILE_SIZE x TILE_SIZE pixels.
+    fn tiles(&self) -> Vec<Tile>{
+        let mut tiles = Vec::new();
+        for y in (0..self.image_height).step_by(TILE_SIZE as usize){
+            for x in (0..self.image_width).step_by(TILE_SIZE as usize){
+                tiles.push(Tile{
+                    x,
+                    y,
+                    width: i32::min(TILE_SIZE, self.image_width - x),
+                    height: i32::min(TILE_SIZE, self.image_height - y)
+                });
+            }
+        }
+        tiles
+    }
+
+    ///Renders the pixels of one tile in row-major order, averaged over all samples.
+    fn render_tile(&self, tile: &Tile, scene: &Scene) -> Vec<Vec3>{
+        let mut pixels = Vec::with_capacity((tile.width*tile.height) as usize);
+        for j in tile.y..tile.y+tile.height{
+            for i in tile.x..tile.x+tile.width{
                 let mut color = Vec3::new(0.0,0.0,0.0);
+                let mut rng = pixel_sampler(self.seed, i, j);
 
                 for _ in 0..self.samples_per_pixel{
-                    let camera_ray = self.get_ray(i, j);
-                    color = color + Camera::ray_color(&camera_ray, scene, self.max_depth);
+                    let camera_ray = self.get_ray(i, j, &mut rng);
+                    color = color + Camera::ray_color(&camera_ray, scene, self.max_depth, None, &[], &mut rng);
                 }
 
-                Camera::write_color(color/self.samples_per_pixel as f64);
+                pixels.push(color/self.samples_per_pixel as f64);
             }
-            progress_bar.inc(1);
         }
-        progress_bar.finish();
+        pixels
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray{
-        let offset = Camera::sample_square();
+    fn get_ray(&self, i: i32, j: i32, rng: &mut Sampler) -> Ray{
+        let offset = Camera::sample_square(rng);
         let pixel_loc = self.pixel00_loc 
             + self.pixel_delta_u*((offset.x+i as f64)) 
             + self.pixel_delta_v*((offset.y+j as f64));
-        let ray_origin = if self.defocus_angle <= 0.0 {self.camera_center} else {self.defocus_disk_sample()};
+        let ray_origin = if self.defocus_angle <= 0.0 {self.camera_center} else {self.defocus_disk_sample(rng)};
         let pixel_direction = pixel_loc - self.camera_center;
-        
-        Ray::new(ray_origin, pixel_direction)
+        //Only draw a random time when the shutter is open for a while, so still renders keep their random sequence.
+        let time = if self.shutter_close > self.shutter_open{
+            self.shutter_open + rng.gen::<f64>()*(self.shutter_close-self.shutter_open)
+        }else{
+            self.shutter_open
+        };
+
+        Ray::new(ray_origin, pixel_direction, time)
     }
 
-    fn sample_square() -> Vec3{
-        let mut rng = rand::thread_rng();
+    fn sample_square(rng: &mut Sampler) -> Vec3{
         Vec3::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5, 0.0)
     }
-    fn defocus_disk_sample(&self) -> Vec3{
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, rng: &mut Sampler) -> Vec3{
+        let p = random_in_unit_disk(rng);
         self.camera_center + self.defocus_disk_u*p.x + self.defocus_disk_v*p.y
     }
 
-    fn ray_color(r: &Ray, scene: &Scene, depth: i32) -> Vec3{
+    ///Estimates the light arriving along r by following one random path through the scene.
+    ///At each diffuse bounce an emitter and an environment map background are also sampled directly with
+    ///shadow rays, and each is combined with the bounce's own sample by multiple importance sampling.
+    ///Point, spot and directional lights are each checked with their own shadow ray. bsdf_pdf is the density
+    ///r was sampled with, or None for camera rays and specular bounces, whose emission is counted in full.
+    ///media holds the absorption coefficients of the transparent objects r is inside, innermost last.
+    fn ray_color(r: &Ray, scene: &Scene, depth: i32, bsdf_pdf: Option<f64>, media: &[Vec3], rng: &mut Sampler) -> Vec3{
         if depth == 0 {
             return Vec3::new(0.0, 0.0, 0.0);
         }
 
-        match scene.intersect(&r, &NEAR_NON_NEG){
-            Some(record) => {
-                match record.material.scatter(&r, &record){
-                    Some((scattered, attenuation)) => {
-                        return Camera::ray_color(&scattered, scene, depth-1)*attenuation;
+        let record = match scene.intersect(r, &NEAR_NON_NEG){
+            Some(record) => record,
+            None => {
+                let color = scene.background.color(r);
+                return match bsdf_pdf{
+                    Some(bsdf_pdf) if scene.background.is_sampled() => {
+                        color*power_heuristic(bsdf_pdf, scene.background.pdf(&r.direction))
+                    }
+                    _ => color
+                };
+            }
+        };
+        let color = Camera::shade(r, &record, scene, depth, bsdf_pdf, media, rng);
+        match media.last(){
+            //Light is absorbed along the way by the innermost object following the Beer-Lambert law.
+            Some(absorption) => {
+                let distance = record.t*r.direction.length();
+                let transmit = |a: f64| if a > 0.0 {f64::exp(-a*distance)} else {1.0};
+                color*Vec3::new(transmit(absorption.x), transmit(absorption.y), transmit(absorption.z))
+            }
+            None => color
+        }
+    }
+
+    ///Returns the light leaving the hit in record back along r.
+    fn shade(r: &Ray, record: &HitRecord, scene: &Scene, depth: i32, bsdf_pdf: Option<f64>, media: &[Vec3], rng: &mut Sampler) -> Vec3{
+        let mut emitted = record.material.emitted(record);
+        if let Some(bsdf_pdf) = bsdf_pdf{
+            if record.material.is_emissive(){
+                emitted = emitted*power_heuristic(bsdf_pdf, scene.pdf_value(&r.origin, &r.direction));
+            }
+        }
+        let scatter = match record.material.scatter(r, record, rng){
+            Some(scatter) => scatter,
+            None => return emitted
+        };
+        match scatter.kind{
+            ScatterKind::Specular(scattered) => {
+                let media = Camera::media_after(record, &scattered, media);
+                emitted + Camera::ray_color(&scattered, scene, depth-1, None, &media, rng)*scatter.attenuation
+            }
+            ScatterKind::Sampled(pdf) => {
+                let mut direct = Vec3::new(0.0, 0.0, 0.0);
+                if scene.has_emitters(){
+                    let emitters = HittablePdf::new(scene, record.p);
+                    let shadow_ray = Ray::new(record.p, emitters.generate(rng), r.time);
+                    let light_pdf = emitters.value(&shadow_ray.direction);
+                    let scattering_pdf = record.material.scattering_pdf(r, record, &shadow_ray);
+                    if light_pdf > 0.0 && scattering_pdf > 0.0{
+                        let tint = record.material.scattering_tint(r, record, &shadow_ray);
+                        //Rays that escape are left to background sampling, and glowing volumes, which are not
+                        //emitters, to the paths that collide with them.
+                        if let Some(light_record) = scene.intersect(&shadow_ray, &NEAR_NON_NEG).filter(|l| l.material.is_emissive()){
+                            let weight = power_heuristic(light_pdf, pdf.value(&shadow_ray.direction));
+                            direct = light_record.material.emitted(&light_record)*scatter.attenuation*tint*(scattering_pdf*weight/light_pdf);
+                        }
                     }
-                    None => {
-                        return Vec3::new(0.0, 0.0, 0.0);
+                }
+                if scene.background.is_sampled(){
+                    let shadow_ray = Ray::new(record.p, scene.background.sample(rng), r.time);
+                    let background_pdf = scene.background.pdf(&shadow_ray.direction);
+                    let scattering_pdf = record.material.scattering_pdf(r, record, &shadow_ray);
+                    if background_pdf > 0.0 && scattering_pdf > 0.0{
+                        let visibility = scene.visibility(&record.p, &shadow_ray.direction, INFINITY, r.time);
+                        let weight = power_heuristic(background_pdf, pdf.value(&shadow_ray.direction));
+                        let tint = record.material.scattering_tint(r, record, &shadow_ray);
+                        direct = direct + scene.background.color(&shadow_ray)*scatter.attenuation*tint*(visibility*scattering_pdf*weight/background_pdf);
                     }
                 }
-                //let normal = record.normal;
-                //let direction = random_on_hemisphere(&normal) + random_unit_vector();
-                //let bounced_ray = Ray::new(record.p, direction);
-                //return Camera::ray_color(&bounced_ray, scene, depth-1)*0.3;
+
+                for light in scene.lights.iter(){
+                    direct = direct + Camera::light_contribution(light, r, record, scatter.attenuation, scene);
+                }
+
+                let scattered = Ray::new(record.p, pdf.generate(rng), r.time);
+                let pdf_value = pdf.value(&scattered.direction);
+                let scattering_pdf = record.material.scattering_pdf(r, record, &scattered);
+                if pdf_value <= 0.0 || scattering_pdf <= 0.0{
+                    return emitted + direct;
+                }
+                let incoming = Camera::ray_color(&scattered, scene, depth-1, Some(pdf_value), media, rng);
+                let tint = record.material.scattering_tint(r, record, &scattered);
+                emitted + direct + incoming*scatter.attenuation*tint*(scattering_pdf/pdf_value)
             }
-            None => {}
         }
-    
-        let unit_direction = unit_vector(&r.direction);
-        let a = unit_direction.y/2.0+0.5;
-        Vec3::new(1.0, 1.0, 1.0)*(1.0-a)+Vec3::new(0.5, 0.7, 1.0)*a
     }
-    
+
+    ///Returns the media a ray scattered specularly from record is inside: one more if it passed into a transparent
+    ///object, one fewer if it passed out of one.
+    fn media_after<'a>(record: &HitRecord, scattered: &Ray, media: &'a [Vec3]) -> Cow<'a, [Vec3]>{
+        //The normal faces the side the incoming ray arrived from.
+        if dot(&scattered.direction, &record.normal) >= 0.0{
+            Cow::Borrowed(media)
+        }else if record.front_face{
+            let mut inside = media.to_vec();
+            inside.push(record.material.absorption());
+            Cow::Owned(inside)
+        }else{
+            Cow::Borrowed(&media[..media.len().saturating_sub(1)])
+        }
+    }
+
+    ///Returns the light reflected back along r from a point, spot or directional light, or black if it is blocked.
+    fn light_contribution(light: &Light, r: &Ray, record: &HitRecord, attenuation: Vec3, scene: &Scene) -> Vec3{
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        let sample = match light.illuminate(&record.p){
+            Some(sample) => sample,
+            None => return black
+        };
+        let shadow_ray = Ray::new(record.p, sample.direction, r.time);
+        let scattering_pdf = record.material.scattering_pdf(r, record, &shadow_ray);
+        if scattering_pdf <= 0.0{
+            return black;
+        }
+        let visibility = scene.visibility(&record.p, &sample.direction, sample.distance, r.time);
+        let tint = record.material.scattering_tint(r, record, &shadow_ray);
+        sample.irradiance*attenuation*tint*(visibility*scattering_pdf)
+    }
+
     fn ray_color_normal(r: &Ray, scene: &Scene) -> Vec3{
         match scene.intersect(&r, &NON_NEG){
             Some(record) => {
@@ -143,26 +369,7 @@ impl Camera{
             None => {}
         }
     
-        let unit_direction = unit_vector(&r.direction);
-        let a = unit_direction.y/2.0+0.5;
-        Vec3::new(1.0, 1.0, 1.0)*(1.0-a)+Vec3::new(0.5, 0.7, 1.0)*a
-    }
-
-    pub fn linear_to_gamma(x: f64) -> f64{
-        if x > 0.0 {return f64::sqrt(x)} else {return 0.0};
-    }
-    pub fn write_color(v: Vec3){
-        let intensity: Interval = Interval { min: 0.0, max: 0.999};
-
-        let r = Camera::linear_to_gamma(v.x);
-        let g = Camera::linear_to_gamma(v.y);
-        let b = Camera::linear_to_gamma(v.z);
-
-        let rbyte = (intensity.clamp(r) * 256.0) as i32;
-        let gbyte = (intensity.clamp(g) * 256.0) as i32;
-        let bbyte = (intensity.clamp(b) * 256.0) as i32;
-
-        println!("{} {} {}", rbyte, gbyte, bbyte);
+        scene.background.color(r)
     }
 
     fn test_gradient(i: i32, j: i32, image_width: i32, image_height: i32) -> Vec3{
@@ -172,4 +379,22 @@ impl Camera{
             0.0
         )
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn keeps_explicit_image_height(){
+        let settings = CameraSettings{image_width: 100, image_height: Some(83), ..CameraSettings::default()};
+        let camera = settings.build();
+        assert_eq!((camera.image_width, camera.image_height), (100, 83));
+        assert!((camera.pixel_delta_u.length()-camera.pixel_delta_v.length()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn derives_image_height_from_aspect_ratio(){
+        let settings = CameraSettings{image_width: 400, aspect_ratio: 2.0, ..CameraSettings::default()};
+        assert_eq!(settings.build().image_height, 200);
+    }
+}
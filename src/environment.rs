@@ -0,0 +1,169 @@
+use rand::Rng;
+use crate::{
+    hittable::Sphere,
+    image::Image,
+    sampler::Sampler,
+    utils::{degrees_to_radians, PI},
+    vec3::{unit_vector, Vec3}
+};
+
+///Returns the luminance of a linear color with Rec. 709 primaries.
+pub fn luminance(c: &Vec3) -> f64{
+    0.2126*c.x + 0.7152*c.y + 0.0722*c.z
+}
+
+///Returns the index of the interval of the cumulative distribution cdf that x falls in.
+fn sample_cdf(cdf: &[f64], x: f64) -> usize{
+    let index = cdf.partition_point(|c| *c <= x);
+    usize::clamp(index, 1, cdf.len()-1) - 1
+}
+
+///Builds a cumulative distribution starting at 0 from weights, normalized to end at 1, and returns it with their total.
+fn cumulative(weights: impl Iterator<Item = f64>) -> (Vec<f64>, f64){
+    let mut cdf = vec![0.0];
+    for w in weights{
+        cdf.push(cdf[cdf.len()-1] + w);
+    }
+    let total = cdf[cdf.len()-1];
+    if total > 0.0{
+        cdf.iter_mut().for_each(|c| *c /= total);
+    }
+    (cdf, total)
+}
+
+///An equirectangular image of the light arriving from every direction, importance-sampled by luminance.
+///The image's left edge faces -x, its center +x, and its top +y, matching Sphere::uv.
+pub struct EnvironmentMap{
+    image: Image,
+    ///Rotation around the y axis in radians.
+    rotation: f64,
+    intensity: f64,
+    ///Cumulative distributions over rows, and over the pixels within each row.
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<Vec<f64>>,
+    ///Sampling weight of each pixel divided by the total weight.
+    pixel_probability: Vec<f64>
+}
+impl EnvironmentMap{
+    ///Returns the map of image turned by rotation degrees around the y axis, with colors scaled by intensity.
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> EnvironmentMap{
+        let (width, height) = (image.width(), image.height());
+        //Rows near the poles cover less solid angle, so pixels are weighted by sin(theta) as well as luminance.
+        let weight = |i: i32, j: i32| {
+            let sin_theta = f64::sin(PI*(j as f64+0.5)/height as f64);
+            f64::max(luminance(&image.get(i, j)), 0.0)*sin_theta
+        };
+        let mut row_weights = Vec::with_capacity(height as usize);
+        let mut column_cdfs = Vec::with_capacity(height as usize);
+        for j in 0..height{
+            let (cdf, total) = cumulative((0..width).map(|i| weight(i, j)));
+            row_weights.push(total);
+            column_cdfs.push(cdf);
+        }
+        let (row_cdf, total) = cumulative(row_weights.into_iter());
+        let pixel_probability = (0..width*height)
+            .map(|index| if total > 0.0 {weight(index % width, index / width)/total} else {0.0})
+            .collect();
+        EnvironmentMap{image, rotation: degrees_to_radians(rotation), intensity, row_cdf, column_cdfs, pixel_probability}
+    }
+    ///Turns a world direction into the map's own frame, or back when inverse is set.
+    fn rotate(&self, d: &Vec3, inverse: bool) -> Vec3{
+        let angle = if inverse {self.rotation} else {-self.rotation};
+        let (sin, cos) = f64::sin_cos(angle);
+        Vec3::new(cos*d.x + sin*d.z, d.y, -sin*d.x + cos*d.z)
+    }
+    ///Returns the pixel seen in direction and the sine of its angle from the y axis.
+    fn pixel(&self, direction: &Vec3) -> (i32, i32, f64){
+        let d = self.rotate(&unit_vector(direction), false);
+        let (u, v) = Sphere::uv(&d);
+        let i = i32::clamp((u*self.image.width() as f64) as i32, 0, self.image.width()-1);
+        let j = i32::clamp(((1.0-v)*self.image.height() as f64) as i32, 0, self.image.height()-1);
+        (i, j, f64::sqrt(f64::max(1.0-d.y*d.y, 0.0)))
+    }
+    pub fn color(&self, direction: &Vec3) -> Vec3{
+        let (i, j, _) = self.pixel(direction);
+        self.image.get(i, j)*self.intensity
+    }
+    ///Returns the density over solid angle with which sample picks direction.
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        let (i, j, sin_theta) = self.pixel(direction);
+        if sin_theta <= 0.0{
+            return 0.0;
+        }
+        let (width, height) = (self.image.width(), self.image.height());
+        //Each pixel covers 1/(width*height) of the (u, v) square, which maps onto 2*pi*pi*sin(theta) of solid angle.
+        self.pixel_probability[(j*width + i) as usize]*(width*height) as f64/(2.0*PI*PI*sin_theta)
+    }
+    ///Returns a random direction, favoring bright parts of the map. Maps that are black everywhere are never sampled.
+    pub fn sample(&self, rng: &mut Sampler) -> Vec3{
+        let j = sample_cdf(&self.row_cdf, rng.gen::<f64>());
+        let i = sample_cdf(&self.column_cdfs[j], rng.gen::<f64>());
+        let u = (i as f64 + rng.gen::<f64>())/self.image.width() as f64;
+        let v = 1.0 - (j as f64 + rng.gen::<f64>())/self.image.height() as f64;
+
+        //Inverts Sphere::uv: theta is measured from -y and phi from -x.
+        let theta = v*PI;
+        let phi = u*2.0*PI - PI;
+        let d = Vec3::new(f64::sin(theta)*f64::cos(phi), -f64::cos(theta), -f64::sin(theta)*f64::sin(phi));
+        self.rotate(&d, true)
+    }
+    pub fn is_black(&self) -> bool{
+        self.row_cdf[self.row_cdf.len()-1] == 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::pdf::tests::check_sampling;
+    use super::*;
+
+    ///A dim map with a bright patch and a black row, so sampling is far from uniform.
+    fn map(rotation: f64) -> EnvironmentMap{
+        let mut image = Image::new(16, 8);
+        for j in 0..8{
+            for i in 0..16{
+                let color = if j == 6 {Vec3::new(0.0, 0.0, 0.0)} else {Vec3::new(0.1, 0.2, 0.1 + i as f64/16.0)};
+                image.set(i, j, color);
+            }
+        }
+        image.set(3, 2, Vec3::new(20.0, 20.0, 20.0));
+        image.set(4, 2, Vec3::new(0.0, 40.0, 0.0));
+        EnvironmentMap::new(image, rotation, 1.0)
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_and_matches_samples(){
+        for rotation in [0.0, 75.0]{
+            let map = map(rotation);
+            //Pixel edges cut through the bins, which the midpoint rule only approximates.
+            assert!((check_sampling(|d| map.pdf(d), |rng| map.sample(rng))-1.0).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn samples_follow_the_rotation(){
+        let (map, turned) = (map(0.0), map(90.0));
+        let d = Vec3::new(0.3, 0.4, -0.8);
+        //Turning the map a quarter turn around y takes d to (d.z, d.y, -d.x) in world space.
+        let e = Vec3::new(d.z, d.y, -d.x);
+        assert!((map.pdf(&d)-turned.pdf(&e)).abs() < 1e-9);
+        assert!((map.color(&d)-turned.color(&e)).length() < 1e-9);
+    }
+
+    #[test]
+    fn never_samples_black_pixels(){
+        let map = map(0.0);
+        let mut rng = <Sampler as rand::SeedableRng>::seed_from_u64(3);
+        for _ in 0..10000{
+            let d = map.sample(&mut rng);
+            assert!(map.pdf(&d) > 0.0);
+            assert!(luminance(&map.color(&d)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn black_maps(){
+        assert!(EnvironmentMap::new(Image::new(4, 2), 0.0, 1.0).is_black());
+        assert!(!map(0.0).is_black());
+    }
+}
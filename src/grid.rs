@@ -0,0 +1,198 @@
+//!Dense voxel grids for heterogeneous volumes.
+//!
+//!A grid file starts with five whitespace-separated header fields: the magic `VA` for ASCII samples
+//!or `VB` for binary ones, the number of voxels along x, y and z, and the number of channels, which is
+//!1 for density alone or 4 for density followed by red, green and blue emission. Comments run from
+//!'#' to the end of the line, in the header and between ASCII samples. ASCII samples follow as decimal
+//!numbers; binary samples follow a single whitespace byte as little-endian 32-bit floats. Voxels are
+//!stored with x varying fastest, then y, then z.
+use std::{fs, io, path::Path};
+use crate::{input::{header_fields, invalid_data}, vec3::Vec3};
+
+///Density and optional emission sampled on a regular grid over the unit cube.
+pub struct Grid{
+    size: [usize; 3],
+    density: Vec<f64>,
+    emission: Option<Vec<Vec3>>
+}
+impl Grid{
+    ///Makes a grid of size voxels from per-voxel values stored with x varying fastest.
+    ///Panics if a value list does not have one entry per voxel.
+    pub fn new(size: [usize; 3], density: Vec<f64>, emission: Option<Vec<Vec3>>) -> Grid{
+        let count = size.iter().try_fold(1usize, |count, n| count.checked_mul(*n)).unwrap_or(0);
+        if count == 0 || density.len() != count || emission.as_ref().is_some_and(|e| e.len() != count){
+            panic!("Grid needs one value per voxel.")
+        }
+        Grid{size, density, emission}
+    }
+    pub fn read(path: &Path) -> io::Result<Grid>{
+        let data = fs::read(path)?;
+        let invalid = |message: &str| invalid_data(format!("{}: {}", path.display(), message));
+
+        let (fields, position) = header_fields(&data, 5);
+        if fields.len() < 5{
+            return Err(invalid("truncated grid header"));
+        }
+        let parse = |field: &str| field.parse::<usize>().map_err(|_| invalid("invalid grid header"));
+        let size = [parse(&fields[1])?, parse(&fields[2])?, parse(&fields[3])?];
+        let channels = parse(&fields[4])?;
+        if size.contains(&0){
+            return Err(invalid("invalid grid size"));
+        }
+        if channels != 1 && channels != 4{
+            return Err(invalid("grids must have 1 or 4 channels"));
+        }
+        let count = size[0].checked_mul(size[1])
+            .and_then(|count| count.checked_mul(size[2]))
+            .filter(|count| count.checked_mul(channels).is_some())
+            .ok_or_else(|| invalid("grid too large"))?;
+
+        let samples: Vec<f64> = match fields[0].as_str(){
+            "VA" => String::from_utf8_lossy(&data[position..])
+                .lines()
+                .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+                .map(|s| s.parse::<f64>().map_err(|_| invalid("invalid grid sample")))
+                .collect::<io::Result<Vec<f64>>>()?,
+            "VB" => data[(position+1).min(data.len())..]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect(),
+            _ => return Err(invalid("not a grid file"))
+        };
+        if samples.len() < count*channels{
+            return Err(invalid("not enough voxel data"));
+        }
+        if samples.iter().any(|x| !x.is_finite() || *x < 0.0){
+            return Err(invalid("grid samples must be finite and not negative"));
+        }
+
+        let voxels = samples.chunks_exact(channels).take(count);
+        let density = voxels.clone().map(|voxel| voxel[0]).collect();
+        let emission = if channels == 4{
+            Some(voxels.map(|voxel| Vec3::new(voxel[1], voxel[2], voxel[3])).collect())
+        }else{
+            None
+        };
+        Ok(Grid{size, density, emission})
+    }
+    pub fn max_density(&self) -> f64{
+        self.density.iter().fold(0.0, |max, d| f64::max(max, *d))
+    }
+    pub fn has_emission(&self) -> bool{
+        self.emission.is_some()
+    }
+    ///Returns the density at p in the unit cube, interpolated between voxel centers.
+    pub fn density(&self, p: &Vec3) -> f64{
+        self.corners(p).iter().map(|(index, weight)| self.density[*index]*weight).sum()
+    }
+    ///Returns the emission at p in the unit cube, interpolated between voxel centers.
+    pub fn emission(&self, p: &Vec3) -> Vec3{
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        match &self.emission{
+            Some(emission) => self.corners(p).iter().fold(black, |sum, (index, weight)| sum + emission[*index]**weight),
+            None => black
+        }
+    }
+    ///Returns the indices and trilinear weights of the eight voxels around p, clamping at the edges.
+    fn corners(&self, p: &Vec3) -> [(usize, f64); 8]{
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for (axis, x) in [p.x, p.y, p.z].into_iter().enumerate(){
+            let last = self.size[axis]-1;
+            let x = (x*self.size[axis] as f64 - 0.5).clamp(0.0, last as f64);
+            lower[axis] = x as usize;
+            upper[axis] = usize::min(lower[axis]+1, last);
+            fraction[axis] = x - lower[axis] as f64;
+        }
+
+        let mut corners = [(0, 0.0); 8];
+        for (n, corner) in corners.iter_mut().enumerate(){
+            let mut index = [0; 3];
+            let mut weight = 1.0;
+            for axis in 0..3{
+                if n >> axis & 1 == 1{
+                    index[axis] = upper[axis];
+                    weight *= fraction[axis];
+                }else{
+                    index[axis] = lower[axis];
+                    weight *= 1.0-fraction[axis];
+                }
+            }
+            *corner = ((index[2]*self.size[1] + index[1])*self.size[0] + index[0], weight);
+        }
+        corners
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::path::PathBuf;
+    use super::*;
+
+    fn write(name: &str, contents: &[u8]) -> PathBuf{
+        let path = std::env::temp_dir().join(format!("grid-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+    fn assert_invalid(result: io::Result<Grid>, message: &str){
+        match result{
+            Ok(_) => panic!("expected an error containing {}", message),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert!(e.to_string().contains(message), "{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_ascii_grids(){
+        let grid = Grid::read(&write("ascii.vol", b"VA # density only\n2 1 1 1\n# x varies fastest\n0.0 4.0\n")).unwrap();
+        assert_eq!(grid.max_density(), 4.0);
+        assert!(!grid.has_emission());
+        //Voxel centers are at 1/4 and 3/4, and values hold still beyond them.
+        assert_eq!(grid.density(&Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(&Vec3::new(0.5, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.density(&Vec3::new(0.625, 0.5, 0.5)), 3.0);
+        assert_eq!(grid.density(&Vec3::new(1.0, 0.0, 1.0)), 4.0);
+        assert_eq!(grid.emission(&Vec3::new(0.5, 0.5, 0.5)).x, 0.0);
+    }
+
+    #[test]
+    fn reads_binary_grids_with_emission(){
+        let mut data = b"VB 1 1 2 4\n".to_vec();
+        for sample in [1.0f32, 0.0, 0.0, 0.0, 3.0, 2.0, 4.0, 6.0]{
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        let grid = Grid::read(&write("binary.vol", &data)).unwrap();
+        assert!(grid.has_emission());
+        assert_eq!(grid.density(&Vec3::new(0.5, 0.5, 0.5)), 2.0);
+        let e = grid.emission(&Vec3::new(0.5, 0.5, 0.75));
+        assert_eq!((e.x, e.y, e.z), (2.0, 4.0, 6.0));
+    }
+
+    #[test]
+    fn interpolates_across_every_axis(){
+        //The density is x + 2y + 4z at voxel centers, which trilinear interpolation reproduces between them.
+        let density = (0..8).map(|n| (n & 1) as f64 + 2.0*(n >> 1 & 1) as f64 + 4.0*(n >> 2 & 1) as f64).collect();
+        let grid = Grid::new([2, 2, 2], density, None);
+        let p = Vec3::new(0.4, 0.5, 0.7);
+        let expected = (p.x-0.25)*2.0 + 2.0*(p.y-0.25)*2.0 + 4.0*(p.z-0.25)*2.0;
+        assert!((grid.density(&p)-expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_malformed_grids(){
+        assert_invalid(Grid::read(&write("overflow.vol", b"VA 3 12297829382473034411 1 1\n1 2 3")), "grid too large");
+        assert_invalid(Grid::read(&write("overflow4.vol", b"VA 2 4611686018427387904 1 4\n1 2 3")), "grid too large");
+        assert_invalid(Grid::read(&write("header.vol", b"VA 1 1 1")), "truncated grid header");
+        assert_invalid(Grid::read(&write("size.vol", b"VA 1 -1 1 1\n0")), "invalid grid header");
+        assert_invalid(Grid::read(&write("zero.vol", b"VA 1 0 1 1\n")), "invalid grid size");
+        assert_invalid(Grid::read(&write("channels.vol", b"VA 1 1 1 3\n0 0 0")), "1 or 4 channels");
+        assert_invalid(Grid::read(&write("short.vol", b"VA 2 1 1 1\n0")), "not enough voxel data");
+        assert_invalid(Grid::read(&write("empty.vol", b"VB 1 1 1 1")), "not enough voxel data");
+        assert_invalid(Grid::read(&write("sample.vol", b"VA 1 1 1 1\nx")), "invalid grid sample");
+        assert_invalid(Grid::read(&write("negative.vol", b"VA 1 1 1 1\n-1")), "finite and not negative");
+        assert_invalid(Grid::read(&write("magic.vol", b"VC 1 1 1 1\n0")), "not a grid file");
+    }
+}
@@ -1,38 +1,113 @@
-use crate::{material::{self, Material}, utils::Interval, vec3::dot, Ray, Vec3};
+use crate::{
+    aabb::{Aabb, EMPTY_BOX},
+    material::Material,
+    motion::Keyframes,
+    onb::Onb,
+    sampler::Sampler,
+    utils::{Interval, NEAR_NON_NEG, PI},
+    vec3::{dot, random_unit_vector},
+    Ray, Vec3
+};
+use rand::Rng;
 use std::sync::Arc;
 pub struct HitRecord{
     pub p: Vec3,
     pub normal: Vec3,
     pub t: f64,
+    ///Surface coordinates of the hit point, used for texture lookups.
+    pub u: f64,
+    pub v: f64,
+    ///A third coordinate for hits inside volumes, which are located by u, v and w; zero on surfaces.
+    pub w: f64,
     pub front_face: bool,
     pub material: Arc<dyn Material>,
 }
 impl HitRecord{
-    pub fn new(p: Vec3, normal: Vec3, t: f64, front_face: bool, material: Arc<dyn Material>) -> HitRecord{
-        HitRecord{p, normal, t, front_face, material}
+    pub fn new(p: Vec3, normal: Vec3, t: f64, u: f64, v: f64, front_face: bool, material: Arc<dyn Material>) -> HitRecord{
+        HitRecord{p, normal, t, u, v, w: 0.0, front_face, material}
     }
-    pub fn generate(p: Vec3, outward_normal: Vec3, t: f64, r: &Ray, material: Arc<dyn Material>) -> HitRecord{
+    pub fn generate(p: Vec3, outward_normal: Vec3, t: f64, u: f64, v: f64, r: &Ray, material: Arc<dyn Material>) -> HitRecord{
         let front_face: bool = dot(&r.direction, &outward_normal) < 0.0;
         let normal: Vec3 = if front_face {outward_normal} else {-outward_normal};
-        HitRecord{p, normal, t, front_face, material}
+        HitRecord{p, normal, t, u, v, w: 0.0, front_face, material}
     }
 }
-pub trait Hittable{
+pub trait Hittable: Send + Sync{
     fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>;
+    ///Returns a box enclosing the whole object, used to build bounding volume hierarchies.
+    fn bounding_box(&self) -> Aabb;
+    ///Returns the density over solid angle with which `random` picks direction from origin.
+    ///Objects that cannot be sampled return 0.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64{
+        0.0
+    }
+    ///Returns a direction from origin toward a random point on the object.
+    fn random(&self, _origin: &Vec3, _rng: &mut Sampler) -> Vec3{
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+    ///Returns true for emitting objects that can be sampled with pdf_value and random, which the scene
+    ///collects into its list of emitters.
+    fn is_emitter(&self) -> bool{
+        false
+    }
+    ///Returns the fraction of light that passes along r within ray_t: 0 if a solid object blocks it, and
+    ///anything from 0 to 1 through volumes. Volumes may return a random estimate whose mean is the true fraction.
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        if self.intersect(r, ray_t).is_some() {0.0} else {1.0}
+    }
+}
+///Lets objects shared with the light list also be stored in the scene.
+impl<T: Hittable + ?Sized> Hittable for Arc<T>{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        self.as_ref().intersect(r, ray_t)
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.as_ref().bounding_box()
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        self.as_ref().pdf_value(origin, direction)
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        self.as_ref().random(origin, rng)
+    }
+    fn is_emitter(&self) -> bool{
+        self.as_ref().is_emitter()
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        self.as_ref().transmittance(r, ray_t)
+    }
 }
 pub struct Sphere{
-    center: Vec3,
+    center: Keyframes<Vec3>,
     radius: f64,
-    material: Arc<dyn Material>
+    material: Arc<dyn Material>,
+    bbox: Aabb
 }
 impl Sphere{
     pub fn new(center: Vec3, radius: f64, material: Arc<dyn Material>) -> Sphere{
-        Sphere{center, radius, material}
+        Sphere::moving(Keyframes::constant(center), radius, material)
+    }
+    ///Returns a sphere whose center follows the keyframes over time.
+    pub fn moving(center: Keyframes<Vec3>, radius: f64, material: Arc<dyn Material>) -> Sphere{
+        let radius_vec = Vec3::new(radius, radius, radius);
+        //The center moves in straight lines between keyframes, so boxes around them cover the whole path.
+        let bbox = center.keys().iter().fold(EMPTY_BOX, |bbox, (_, c)| {
+            Aabb::surrounding(&bbox, &Aabb::from_points(*c-radius_vec, *c+radius_vec))
+        });
+        Sphere{center, radius, material, bbox}
+    }
+    ///Returns the (u, v) coordinates of a point on the unit sphere: u is the angle around the
+    ///y axis starting from -x, and v the angle from -y to +y, both scaled to [0, 1].
+    pub fn uv(p: &Vec3) -> (f64, f64){
+        let theta = f64::acos(-p.y);
+        let phi = f64::atan2(-p.z, p.x) + PI;
+        (phi/(2.0*PI), theta/PI)
     }
 }
 impl Hittable for Sphere{
     fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
-        let oc: Vec3 = self.center-r.origin;
+        let center = self.center.at(r.time);
+        let oc: Vec3 = center-r.origin;
         let a: f64 = r.direction.length_squared();
         let h: f64 = dot(&r.direction, &oc);
         let c: f64 = oc.length_squared() - self.radius * self.radius;
@@ -50,13 +125,49 @@ impl Hittable for Sphere{
                 }
             }
             let p: Vec3 = r.at(root);
+            let outward_normal = (p-center)/self.radius;
+            let (u, v) = Sphere::uv(&outward_normal);
             return Some(HitRecord::generate(
                 p,
-                (p-self.center)/self.radius,
+                outward_normal,
                 root,
+                u,
+                v,
                 r,
                 self.material.clone()
             ));
         }
     }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    ///Samples the cone of directions the sphere covers as seen from origin. Moving spheres are
+    ///sampled where they are at time 0.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        if self.intersect(&Ray::new(*origin, *direction, 0.0), &NEAR_NON_NEG).is_none(){
+            return 0.0;
+        }
+        let distance_squared = (self.center.at(0.0)-*origin).length_squared();
+        if distance_squared <= self.radius*self.radius{
+            return 1.0/(4.0*PI);
+        }
+        let cos_theta_max = f64::sqrt(1.0 - self.radius*self.radius/distance_squared);
+        1.0/(2.0*PI*(1.0-cos_theta_max))
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        let direction = self.center.at(0.0)-*origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius*self.radius{
+            return random_unit_vector(rng);
+        }
+        //Picks a direction uniformly within the cone, around +z, then turns it toward the center.
+        let cos_theta_max = f64::sqrt(1.0 - self.radius*self.radius/distance_squared);
+        let z = 1.0 + rng.gen::<f64>()*(cos_theta_max-1.0);
+        let phi = 2.0*PI*rng.gen::<f64>();
+        let sin_theta = f64::sqrt(1.0-z*z);
+        Onb::new(&direction).transform(&Vec3::new(f64::cos(phi)*sin_theta, f64::sin(phi)*sin_theta, z))
+    }
+    fn is_emitter(&self) -> bool{
+        self.material.is_emissive()
+    }
 }
\ No newline at end of file
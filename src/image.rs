@@ -0,0 +1,34 @@
+use crate::vec3::Vec3;
+
+///A rectangular buffer of linear, unclamped RGB pixels stored in row-major order.
+pub struct Image{
+    width: i32,
+    height: i32,
+    pixels: Vec<Vec3>
+}
+impl Image{
+    ///Returns a black image of the given size. Panics if either size is negative.
+    pub fn new(width: i32, height: i32) -> Image{
+        if width < 0 || height < 0{
+            panic!("Image sizes must not be negative.")
+        }
+        let pixels = vec![Vec3::new(0.0, 0.0, 0.0); width as usize*height as usize];
+        Image{width, height, pixels}
+    }
+    pub fn width(&self) -> i32{
+        self.width
+    }
+    pub fn height(&self) -> i32{
+        self.height
+    }
+    pub fn get(&self, i: i32, j: i32) -> Vec3{
+        self.pixels[j as usize*self.width as usize + i as usize]
+    }
+    pub fn set(&mut self, i: i32, j: i32, color: Vec3){
+        self.pixels[j as usize*self.width as usize + i as usize] = color;
+    }
+    ///Returns the pixels row by row, starting from the top left.
+    pub fn pixels(&self) -> &[Vec3]{
+        &self.pixels
+    }
+}
@@ -0,0 +1,459 @@
+use std::{fs::{self, File}, io, path::Path};
+use crate::{image::Image, vec3::Vec3};
+
+///Undoes the gamma encoding applied by output::linear_to_gamma.
+pub fn gamma_to_linear(x: f64) -> f64{
+    x*x
+}
+pub(crate) fn invalid_data(message: String) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+///Splits up to count whitespace-separated fields off the start of data, skipping comments, which run from
+///'#' to the end of the line. Returns the fields, fewer if data ends first, and the position just after the last.
+pub(crate) fn header_fields(data: &[u8], count: usize) -> (Vec<String>, usize){
+    let mut fields: Vec<String> = Vec::new();
+    let mut position = 0;
+    while fields.len() < count && position < data.len(){
+        let c = data[position];
+        if c == b'#'{
+            while position < data.len() && data[position] != b'\n'{
+                position += 1;
+            }
+        }else if c.is_ascii_whitespace(){
+            position += 1;
+        }else{
+            let start = position;
+            while position < data.len() && !data[position].is_ascii_whitespace(){
+                position += 1;
+            }
+            fields.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+        }
+    }
+    (fields, position)
+}
+
+///Reads a PNG file into linear colors. Any alpha channel is dropped.
+pub fn read_png(path: &Path) -> io::Result<Image>{
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let data = &buffer[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let samples: Vec<f64> = match info.bit_depth{
+        png::BitDepth::Sixteen => data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f64/65535.0).collect(),
+        _ => data.iter().map(|b| *b as f64/255.0).collect()
+    };
+
+    let mut image = Image::new(info.width as i32, info.height as i32);
+    for (index, pixel) in samples.chunks_exact(channels).enumerate(){
+        //Grayscale images have one or two channels; color images have three or four.
+        let (r, g, b) = if channels < 3 {(pixel[0], pixel[0], pixel[0])} else {(pixel[0], pixel[1], pixel[2])};
+        let i = index as i32 % image.width();
+        let j = index as i32 / image.width();
+        image.set(i, j, Vec3::new(gamma_to_linear(r), gamma_to_linear(g), gamma_to_linear(b)));
+    }
+    Ok(image)
+}
+
+///Reads an ASCII (P3) or binary (P6) PPM file into linear colors.
+pub fn read_ppm(path: &Path) -> io::Result<Image>{
+    let data = fs::read(path)?;
+
+    //The header is the magic number, the width, the height and the maximum sample value.
+    let (fields, position) = header_fields(&data, 4);
+    if fields.len() < 4{
+        return Err(invalid_data(format!("{}: truncated PPM header", path.display())));
+    }
+    let parse = |field: &str| field.parse::<usize>().map_err(|_| invalid_data(format!("{}: invalid PPM header", path.display())));
+    let (width, height, max) = (parse(&fields[1])?, parse(&fields[2])?, parse(&fields[3])?);
+    if width == 0 || height == 0{
+        return Err(invalid_data(format!("{}: invalid PPM size", path.display())));
+    }
+    if max == 0 || max > 65535{
+        return Err(invalid_data(format!("{}: invalid PPM maximum", path.display())));
+    }
+    //Checked before reading any samples, so a huge header cannot overflow or allocate.
+    let count = width.checked_mul(height)
+        .filter(|count| count.checked_mul(3).is_some() && i32::try_from(width).is_ok() && i32::try_from(height).is_ok())
+        .ok_or_else(|| invalid_data(format!("{}: PPM too large", path.display())))?;
+    let max = max as f64;
+    if position >= data.len(){
+        return Err(invalid_data(format!("{}: not enough pixel data", path.display())));
+    }
+
+    let samples: Vec<f64> = match fields[0].as_str(){
+        "P3" => String::from_utf8_lossy(&data[position..])
+            .split_whitespace()
+            .map(|s| s.parse::<f64>().map(|x| x/max).map_err(|_| invalid_data(format!("{}: invalid PPM sample", path.display()))))
+            .collect::<io::Result<Vec<f64>>>()?,
+        //A single whitespace byte separates the header from binary data.
+        "P6" if max < 256.0 => data[position+1..].iter().map(|b| *b as f64/max).collect(),
+        "P6" => data[position+1..].chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f64/max).collect(),
+        magic => return Err(invalid_data(format!("{}: unsupported PPM type {}", path.display(), magic)))
+    };
+    if samples.len() < count*3{
+        return Err(invalid_data(format!("{}: not enough pixel data", path.display())));
+    }
+
+    let mut image = Image::new(width as i32, height as i32);
+    for (index, pixel) in samples.chunks_exact(3).take(count).enumerate(){
+        let color = Vec3::new(gamma_to_linear(pixel[0]), gamma_to_linear(pixel[1]), gamma_to_linear(pixel[2]));
+        image.set((index % width) as i32, (index / width) as i32, color);
+    }
+    Ok(image)
+}
+
+///Reads a color (PF) or grayscale (Pf) PFM file. Its samples are already linear.
+pub fn read_pfm(path: &Path) -> io::Result<Image>{
+    let data = fs::read(path)?;
+    let invalid = |message: &str| invalid_data(format!("{}: {}", path.display(), message));
+
+    //The header is three whitespace-separated fields after the magic, ending with a single whitespace byte.
+    let mut fields: Vec<&[u8]> = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 && position < data.len(){
+        if data[position].is_ascii_whitespace(){
+            position += 1;
+            continue;
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace(){
+            position += 1;
+        }
+        fields.push(&data[start..position]);
+    }
+    if fields.len() < 4{
+        return Err(invalid("truncated PFM header"));
+    }
+    let channels = match fields[0]{
+        b"PF" => 3,
+        b"Pf" => 1,
+        _ => return Err(invalid("not a PFM file"))
+    };
+    let parse = |field: &[u8]| String::from_utf8_lossy(field).parse::<f64>().map_err(|_| invalid("invalid PFM header"));
+    let (width, height, scale) = (parse(fields[1])? as i32, parse(fields[2])? as i32, parse(fields[3])?);
+    if width <= 0 || height <= 0{
+        return Err(invalid("invalid PFM size"));
+    }
+    if position >= data.len(){
+        return Err(invalid("not enough pixel data"));
+    }
+    //A negative scale marks little-endian samples.
+    let samples: Vec<f64> = data[position+1..]
+        .chunks_exact(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            (if scale < 0.0 {f32::from_le_bytes(bytes)} else {f32::from_be_bytes(bytes)}) as f64
+        })
+        .collect();
+    if samples.len() < width as usize*height as usize*channels as usize{
+        return Err(invalid("not enough pixel data"));
+    }
+
+    //Rows are stored from the bottom up.
+    let mut image = Image::new(width, height);
+    for (index, pixel) in samples.chunks_exact(channels as usize).take((width*height) as usize).enumerate(){
+        let color = if channels == 1 {Vec3::new(pixel[0], pixel[0], pixel[0])} else {Vec3::new(pixel[0], pixel[1], pixel[2])};
+        image.set(index as i32 % width, height - 1 - index as i32 / width, color);
+    }
+    Ok(image)
+}
+
+///Unpacks Radiance's shared-exponent RGBE encoding, the inverse of output::to_rgbe.
+pub fn from_rgbe(rgbe: [u8; 4]) -> Vec3{
+    if rgbe[3] == 0{
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let scale = f64::powi(2.0, rgbe[3] as i32 - 136);
+    Vec3::new(rgbe[0] as f64*scale, rgbe[1] as f64*scale, rgbe[2] as f64*scale)
+}
+
+///Reads a Radiance RGBE (.hdr) file with flat or run-length encoded scanlines, stored top to bottom.
+pub fn read_hdr(path: &Path) -> io::Result<Image>{
+    let data = fs::read(path)?;
+    let invalid = |message: &str| invalid_data(format!("{}: {}", path.display(), message));
+
+    //Header lines run up to a blank line, followed by the resolution line.
+    let mut lines: Vec<String> = Vec::new();
+    let mut position = 0;
+    while lines.len() < 2 || !lines[lines.len()-2].is_empty(){
+        let end = data[position..].iter().position(|b| *b == b'\n').ok_or_else(|| invalid("truncated header"))?;
+        lines.push(String::from_utf8_lossy(&data[position..position+end]).into_owned());
+        position += end+1;
+    }
+    if !lines[0].starts_with("#?"){
+        return Err(invalid("not a Radiance file"));
+    }
+    if lines.iter().any(|line| line.starts_with("FORMAT=") && line.as_str() != "FORMAT=32-bit_rle_rgbe"){
+        return Err(invalid("unsupported pixel format"));
+    }
+    let resolution: Vec<&str> = lines[lines.len()-1].split_whitespace().collect();
+    let (height, width) = match resolution.as_slice(){
+        ["-Y", height, "+X", width] => (
+            height.parse::<i32>().map_err(|_| invalid("invalid resolution"))?,
+            width.parse::<i32>().map_err(|_| invalid("invalid resolution"))?
+        ),
+        _ => return Err(invalid("unsupported resolution line"))
+    };
+    if width <= 0 || height <= 0{
+        return Err(invalid("invalid resolution"));
+    }
+    //Each scanline takes at least 4 bytes per pixel, or when run-length encoded 4 bytes of marker and
+    //2 bytes per run of up to 127 values in each channel, so the file must be long enough before allocating.
+    let line_bytes = if (8..0x8000).contains(&width) {4 + 8*(width as usize).div_ceil(127)} else {4*width as usize};
+    if (height as usize).checked_mul(line_bytes).is_none_or(|bytes| bytes > data.len()-position){
+        return Err(invalid("not enough pixel data"));
+    }
+
+    let mut image = Image::new(width, height);
+    let mut next = |count: usize| -> io::Result<&[u8]>{
+        let bytes = data.get(position..position+count).ok_or_else(|| invalid("not enough pixel data"))?;
+        position += count;
+        Ok(bytes)
+    };
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for j in 0..height{
+        let start = next(4)?;
+        //New-style run-length encoding stores each channel separately, marked by 2, 2 and the width.
+        if (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && ((start[2] as i32) << 8 | start[3] as i32) == width{
+            for channel in 0..4{
+                let mut i = 0;
+                while i < width as usize{
+                    let count = next(1)?[0] as usize;
+                    let (run, literal) = if count > 128 {(count-128, false)} else {(count, true)};
+                    if run == 0 || i+run > width as usize{
+                        return Err(invalid("bad run-length encoding"));
+                    }
+                    if literal{
+                        for (k, value) in next(run)?.iter().enumerate(){
+                            scanline[i+k][channel] = *value;
+                        }
+                    }else{
+                        let value = next(1)?[0];
+                        for pixel in scanline[i..i+run].iter_mut(){
+                            pixel[channel] = value;
+                        }
+                    }
+                    i += run;
+                }
+            }
+        }else{
+            scanline[0] = [start[0], start[1], start[2], start[3]];
+            for pixel in scanline.iter_mut().skip(1){
+                let bytes = next(4)?;
+                *pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            }
+        }
+        for (i, rgbe) in scanline.iter().enumerate(){
+            image.set(i as i32, j, from_rgbe(*rgbe));
+        }
+    }
+    Ok(image)
+}
+
+///Loads an image from path, choosing the format from the file extension.
+pub fn load(path: &Path) -> io::Result<Image>{
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref(){
+        Some("png") => read_png(path),
+        Some("ppm") => read_ppm(path),
+        Some("pfm") => read_pfm(path),
+        Some("hdr") => read_hdr(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported image format: {}", path.display())
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use std::path::PathBuf;
+    use crate::output::{self, PngDepth};
+    use super::*;
+
+    fn write(name: &str, contents: &[u8]) -> PathBuf{
+        let path = std::env::temp_dir().join(format!("input-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+    fn assert_near(a: Vec3, b: Vec3, tolerance: f64){
+        assert!((a.x-b.x).abs() < tolerance && (a.y-b.y).abs() < tolerance && (a.z-b.z).abs() < tolerance,
+            "{} {} {} != {} {} {}", a.x, a.y, a.z, b.x, b.y, b.z);
+    }
+    fn assert_invalid(result: io::Result<Image>, message: &str){
+        match result{
+            Ok(_) => panic!("expected an error containing {}", message),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert!(e.to_string().contains(message), "{}", e);
+            }
+        }
+    }
+    fn test_image() -> Image{
+        let mut image = Image::new(3, 2);
+        for j in 0..2{
+            for i in 0..3{
+                image.set(i, j, Vec3::new(i as f64/3.0, j as f64/2.0, 0.25));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn reads_ascii_ppm(){
+        let path = write("ascii.ppm", b"P3\n# a comment\n2 1\n255\n255 0 0\n0 0 255\n");
+        let image = read_ppm(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_near(image.get(0, 0), Vec3::new(1.0, 0.0, 0.0), 1e-12);
+        assert_near(image.get(1, 0), Vec3::new(0.0, 0.0, 1.0), 1e-12);
+    }
+
+    #[test]
+    fn reads_binary_ppm(){
+        let path = write("binary.ppm", b"P6\n1 2\n255\n\xff\x00\x80\x00\xff\x00");
+        let image = read_ppm(&path).unwrap();
+        assert_near(image.get(0, 0), Vec3::new(1.0, 0.0, gamma_to_linear(128.0/255.0)), 1e-12);
+        assert_near(image.get(0, 1), Vec3::new(0.0, 1.0, 0.0), 1e-12);
+
+        let path = write("wide.ppm", b"P6\n1 1\n65535\n\xff\xff\x00\x00\x80\x00");
+        let image = read_ppm(&path).unwrap();
+        assert_near(image.get(0, 0), Vec3::new(1.0, 0.0, gamma_to_linear(32768.0/65535.0)), 1e-12);
+    }
+
+    #[test]
+    fn round_trips_written_ppm(){
+        let mut data = Vec::new();
+        output::write_ppm(&test_image(), &mut data).unwrap();
+        let image = read_ppm(&write("round.ppm", &data)).unwrap();
+        for j in 0..2{
+            for i in 0..3{
+                assert_near(image.get(i, j), test_image().get(i, j), 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_written_png(){
+        for (name, depth, tolerance) in [("round8.png", PngDepth::Eight, 0.01), ("round16.png", PngDepth::Sixteen, 1e-4)]{
+            let path = std::env::temp_dir().join(format!("input-{}-{}", std::process::id(), name));
+            output::write_png(&test_image(), File::create(&path).unwrap(), depth).unwrap();
+            let image = load(&path).unwrap();
+            for j in 0..2{
+                for i in 0..3{
+                    assert_near(image.get(i, j), test_image().get(i, j), tolerance);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_ppm(){
+        assert_invalid(read_ppm(&write("header.ppm", b"P6\n1 1")), "truncated PPM header");
+        assert_invalid(read_ppm(&write("empty.ppm", b"P6\n1 1\n255")), "not enough pixel data");
+        assert_invalid(read_ppm(&write("short.ppm", b"P6\n2 1\n255\n\x00\x00\x00")), "not enough pixel data");
+        assert_invalid(read_ppm(&write("ascii_short.ppm", b"P3\n2 1\n255\n0 0 0\n")), "not enough pixel data");
+        assert_invalid(read_ppm(&write("zero.ppm", b"P6\n0 1\n255\n")), "invalid PPM size");
+        assert_invalid(read_ppm(&write("negative.ppm", b"P3\n-1 1\n255\n0 0 0\n")), "invalid PPM header");
+        assert_invalid(read_ppm(&write("huge.ppm", b"P6 50000 50000 255\n\x00\x00\x00")), "not enough pixel data");
+        assert_invalid(read_ppm(&write("overflow.ppm", b"P6 4294967296 4294967296 255\n\x00")), "PPM too large");
+        assert_invalid(read_ppm(&write("wide.ppm", b"P6 3000000000 1 255\n\x00")), "PPM too large");
+        assert_invalid(read_ppm(&write("max.ppm", b"P6 1 1 0\n\x00\x00\x00")), "invalid PPM maximum");
+        assert_invalid(read_ppm(&write("sample.ppm", b"P3\n1 1\n255\n0 x 0\n")), "invalid PPM sample");
+        assert_invalid(read_ppm(&write("type.ppm", b"P5\n1 1\n255\n\x00")), "unsupported PPM type");
+    }
+
+    #[test]
+    fn round_trips_written_pfm(){
+        let mut data = Vec::new();
+        output::write_pfm(&test_image(), &mut data).unwrap();
+        let image = load(&write("round.pfm", &data)).unwrap();
+        for j in 0..2{
+            for i in 0..3{
+                assert_near(image.get(i, j), test_image().get(i, j), 1e-7);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_big_endian_grayscale_pfm(){
+        //Rows are stored bottom up, so the first sample is the lower pixel.
+        let mut data = b"Pf\n1 2\n1.0\n".to_vec();
+        data.extend_from_slice(&0.5f32.to_be_bytes());
+        data.extend_from_slice(&2.0f32.to_be_bytes());
+        let image = read_pfm(&write("gray.pfm", &data)).unwrap();
+        assert_near(image.get(0, 1), Vec3::new(0.5, 0.5, 0.5), 1e-12);
+        assert_near(image.get(0, 0), Vec3::new(2.0, 2.0, 2.0), 1e-12);
+    }
+
+    #[test]
+    fn rejects_malformed_pfm(){
+        assert_invalid(read_pfm(&write("empty.pfm", b"PF\n1 1\n-1.0")), "not enough pixel data");
+        assert_invalid(read_pfm(&write("short.pfm", b"PF\n1 1\n-1.0\n\x00\x00\x00\x00")), "not enough pixel data");
+        assert_invalid(read_pfm(&write("huge.pfm", b"PF\n70000 70000\n-1.0\n\x00\x00\x00\x00")), "not enough pixel data");
+        assert_invalid(read_pfm(&write("zero.pfm", b"PF\n0 1\n-1.0\n")), "invalid PFM size");
+        assert_invalid(read_pfm(&write("header.pfm", b"PF\n1 1")), "truncated PFM header");
+        assert_invalid(read_pfm(&write("magic.pfm", b"PG\n1 1\n-1.0\n\x00\x00\x00\x00")), "not a PFM file");
+        assert_invalid(read_pfm(&write("scale.pfm", b"PF\n1 1\nbig\n\x00\x00\x00\x00")), "invalid PFM header");
+    }
+
+    #[test]
+    fn round_trips_written_hdr(){
+        let mut data = Vec::new();
+        output::write_hdr(&test_image(), &mut data).unwrap();
+        let image = load(&write("round.hdr", &data)).unwrap();
+        for j in 0..2{
+            for i in 0..3{
+                //RGBE keeps 8 bits of mantissa.
+                assert_near(image.get(i, j), test_image().get(i, j), 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_run_length_encoded_hdr(){
+        //Two scanlines of eight pixels: each channel is one run, except green in the second, which is literal.
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8, 128+8, 128, 128+8, 64, 128+8, 0, 128+8, 129]);
+        data.extend_from_slice(&[2, 2, 0, 8, 128+8, 0, 8, 0, 32, 64, 96, 128, 160, 192, 224, 128+8, 0, 128+8, 128]);
+        let image = read_hdr(&write("rle.hdr", &data)).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 2));
+        for i in 0..8{
+            assert_near(image.get(i, 0), from_rgbe([128, 64, 0, 129]), 1e-12);
+            assert_near(image.get(i, 1), from_rgbe([0, 32*i as u8, 0, 128]), 1e-12);
+        }
+        assert_near(image.get(0, 0), Vec3::new(1.0, 0.5, 0.0), 1e-12);
+    }
+
+    #[test]
+    fn rejects_huge_hdr_before_allocating(){
+        let hdr = |resolution: &[u8]| [&b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n"[..], resolution, b"\x02\x02\x00\x08"].concat();
+        assert_invalid(read_hdr(&write("overflow.hdr", &hdr(b"-Y 100000 +X 100000\n"))), "not enough pixel data");
+        assert_invalid(read_hdr(&write("tall.hdr", &hdr(b"-Y 2000000000 +X 1\n"))), "not enough pixel data");
+        assert_invalid(read_hdr(&write("wide.hdr", &hdr(b"-Y 1 +X 2000000000\n"))), "not enough pixel data");
+        assert_invalid(read_hdr(&write("rle.hdr", &hdr(b"-Y 3 +X 1000\n"))), "not enough pixel data");
+        assert_invalid(read_hdr(&write("bigger.hdr", &hdr(b"-Y 1 +X 99999999999\n"))), "invalid resolution");
+    }
+
+    #[test]
+    fn rejects_malformed_hdr(){
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+        let hdr = |resolution: &[u8], pixels: &[u8]| [&header[..], resolution, pixels].concat();
+        assert_invalid(read_hdr(&write("zero.hdr", &hdr(b"-Y 0 +X 0\n", b""))), "invalid resolution");
+        assert_invalid(read_hdr(&write("negative.hdr", &hdr(b"-Y 1 +X -4\n", b""))), "invalid resolution");
+        assert_invalid(read_hdr(&write("flipped.hdr", &hdr(b"+Y 1 +X 1\n", b"\x80\x80\x80\x80"))), "unsupported resolution line");
+        assert_invalid(read_hdr(&write("short.hdr", &hdr(b"-Y 1 +X 2\n", b"\x80\x80\x80\x80"))), "not enough pixel data");
+        assert_invalid(read_hdr(&write("run.hdr", &hdr(b"-Y 1 +X 8\n", &[2, 2, 0, 8, 128+9, 0, 0, 0, 0, 0, 0, 0]))), "bad run-length encoding");
+        assert_invalid(read_hdr(&write("truncated.hdr", b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n")), "truncated header");
+        assert_invalid(read_hdr(&write("magic.hdr", b"P6\n\n-Y 1 +X 1\n\x80\x80\x80\x80")), "not a Radiance file");
+        assert_invalid(read_hdr(&write("format.hdr", b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x80\x80\x80\x80")), "unsupported pixel format");
+    }
+
+    #[test]
+    fn rejects_unknown_extensions(){
+        assert!(load(Path::new("image.gif")).is_err_and(|e| e.kind() == io::ErrorKind::InvalidInput));
+    }
+}
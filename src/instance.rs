@@ -0,0 +1,195 @@
+use std::sync::Arc;
+use crate::{
+    aabb::Aabb,
+    hittable::HitRecord,
+    matrix::Mat4,
+    motion::{animated_box, transform_box, Keyframes, Pose},
+    sampler::Sampler,
+    utils::Interval,
+    vec3::unit_vector,
+    Hittable, Ray, Vec3
+};
+
+///A shared object placed in the scene by an affine transform, so the same geometry can appear many times.
+pub struct Instance{
+    object: Arc<dyn Hittable>,
+    to_world: Mat4,
+    to_object: Mat4,
+    ///Poses replacing the fixed transforms for animated instances.
+    poses: Option<Keyframes<Pose>>,
+    bbox: Aabb
+}
+impl Instance{
+    ///Places object in the scene by transform, which maps object space to world space.
+    ///Returns None if the transform is singular.
+    pub fn new(object: Arc<dyn Hittable>, transform: Mat4) -> Option<Instance>{
+        let to_object = transform.inverse()?;
+        let bbox = transform_box(&object.bounding_box(), &transform);
+        Some(Instance{object, to_world: transform, to_object, poses: None, bbox})
+    }
+    ///Places object by poses interpolated at each ray's time, so it blurs as it moves.
+    ///Returns None if any pose has a zero scale, or if a scale changes sign between two poses,
+    ///since the linearly interpolated scale passes through zero on the way.
+    pub fn animated(object: Arc<dyn Hittable>, poses: Keyframes<Pose>) -> Option<Instance>{
+        let keys = poses.keys();
+        if !keys.iter().all(|(_, pose)| pose.is_invertible()){
+            return None;
+        }
+        let flips = |(a, b): (f64, f64)| a*b < 0.0;
+        if keys.windows(2).any(|pair| {
+            let (a, b) = (pair[0].1.scale, pair[1].1.scale);
+            [(a.x, b.x), (a.y, b.y), (a.z, b.z)].into_iter().any(flips)
+        }){
+            return None;
+        }
+        let bbox = animated_box(&object.bounding_box(), &poses);
+        Some(Instance{object, to_world: Mat4::identity(), to_object: Mat4::identity(), poses: Some(poses), bbox})
+    }
+    ///Returns the object-to-world transform and its inverse at the given time.
+    fn transforms(&self, time: f64) -> (Mat4, Mat4){
+        match &self.poses{
+            Some(poses) => {
+                let pose = poses.at(time);
+                (pose.matrix(), pose.inverse_matrix())
+            }
+            None => (self.to_world, self.to_object)
+        }
+    }
+    ///Returns r in object space. The direction is left unnormalized so that t means the same thing in both spaces.
+    fn object_ray(r: &Ray, to_object: &Mat4) -> Ray{
+        Ray::new(to_object.transform_point(&r.origin), to_object.transform_vector(&r.direction), r.time)
+    }
+}
+impl Hittable for Instance{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let (to_world, to_object) = self.transforms(r.time);
+        let object_ray = Instance::object_ray(r, &to_object);
+        let mut record = self.object.intersect(&object_ray, ray_t)?;
+        record.p = to_world.transform_point(&record.p);
+        //Normals transform by the inverse transpose, which keeps their side relative to the ray.
+        record.normal = unit_vector(&to_object.transpose().transform_vector(&record.normal));
+        Some(record)
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        let (_, to_object) = self.transforms(r.time);
+        self.object.transmittance(&Instance::object_ray(r, &to_object), ray_t)
+    }
+    ///Animated instances are sampled in their pose at time 0, as moving spheres are.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        let (to_world, to_object) = self.transforms(0.0);
+        let object_direction = to_object.transform_vector(&unit_vector(direction));
+        let pdf = self.object.pdf_value(&to_object.transform_point(origin), &object_direction);
+        //A transform A stretches the solid angle around the unit direction w by |det A|/|Aw|^3, and here
+        //w is along object_direction, so |Aw| is 1/|object_direction|.
+        pdf/(f64::abs(to_world.linear_determinant())*object_direction.length().powi(3))
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        let (to_world, to_object) = self.transforms(0.0);
+        to_world.transform_vector(&self.object.random(&to_object.transform_point(origin), rng))
+    }
+    fn is_emitter(&self) -> bool{
+        self.object.is_emitter()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{hittable::Sphere, material::{DiffuseLight, Lambertian}, matrix::Quaternion, pdf::tests::check_sampling, quad::Quad, utils::PI, vec3::dot};
+    use super::*;
+
+    fn sphere() -> Arc<dyn Hittable>{
+        Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))))
+    }
+    fn hit(object: &dyn Hittable, origin: Vec3, direction: Vec3) -> HitRecord{
+        object.intersect(&Ray::new(origin, direction, 0.0), &Interval::new(0.001, f64::INFINITY)).unwrap()
+    }
+    fn assert_near(a: Vec3, b: Vec3){
+        assert!((a-b).length() < 1e-9, "{} {} {} != {} {} {}", a.x, a.y, a.z, b.x, b.y, b.z);
+    }
+
+    #[test]
+    fn translates_hits(){
+        let instance = Instance::new(sphere(), Mat4::translation(Vec3::new(5.0, 0.0, 0.0))).unwrap();
+        let record = hit(&instance, Vec3::new(5.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((record.t-9.0).abs() < 1e-9);
+        assert_near(record.p, Vec3::new(5.0, 0.0, 1.0));
+        assert_near(record.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn keeps_t_for_scaled_objects(){
+        let instance = Instance::new(sphere(), Mat4::scaling(Vec3::new(2.0, 2.0, 2.0))).unwrap();
+        let record = hit(&instance, Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!((record.t-4.0).abs() < 1e-9);
+        assert_near(record.p, Vec3::new(0.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scaling(){
+        //The ellipsoid x*x/4 + y*y + z*z = 1, whose normals are along (x/4, y, z).
+        let instance = Instance::new(sphere(), Mat4::scaling(Vec3::new(2.0, 1.0, 1.0))).unwrap();
+        let record = hit(&instance, Vec3::new(1.0, 0.5, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let p = record.p;
+        assert!((p.x*p.x/4.0 + p.y*p.y + p.z*p.z - 1.0).abs() < 1e-9);
+        assert_near(record.normal, unit_vector(&Vec3::new(p.x/4.0, p.y, p.z)));
+        assert!(record.front_face);
+    }
+
+    #[test]
+    fn rotates_normals(){
+        let quad: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+        ));
+        //A quarter turn around y takes the quad's +z normal to +x.
+        let instance = Instance::new(quad, Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), PI/2.0)).unwrap();
+        let record = hit(&instance, Vec3::new(5.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0));
+        assert_near(record.p, Vec3::new(0.0, 0.5, 0.5));
+        assert_near(record.normal, Vec3::new(1.0, 0.0, 0.0));
+        assert!(record.front_face);
+        let back = hit(&instance, Vec3::new(-5.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(!back.front_face);
+        assert!(dot(&back.normal, &Vec3::new(1.0, 0.0, 0.0)) < -0.999);
+    }
+
+    #[test]
+    fn bounds_the_transformed_object(){
+        let transform = Mat4::translation(Vec3::new(0.0, 3.0, 0.0))*Mat4::scaling(Vec3::new(1.0, 2.0, 1.0));
+        let bbox = Instance::new(sphere(), transform).unwrap().bounding_box();
+        assert!((bbox.y.min-1.0).abs() < 1e-9 && (bbox.y.max-5.0).abs() < 1e-9);
+        assert!((bbox.x.min+1.0).abs() < 1e-9 && (bbox.x.max-1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_singular_transforms(){
+        assert!(Instance::new(sphere(), Mat4::scaling(Vec3::new(1.0, 0.0, 1.0))).is_none());
+    }
+
+    #[test]
+    fn rejects_scales_that_pass_through_zero(){
+        let pose = |scale| Pose{translation: Vec3::new(0.0, 0.0, 0.0), rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0), scale};
+        let animated = |end| Instance::animated(sphere(), Keyframes::linear(pose(Vec3::new(1.0, 2.0, 1.0)), pose(end)));
+        assert!(animated(Vec3::new(1.0, 0.5, 3.0)).is_some());
+        assert!(animated(Vec3::new(1.0, -2.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn samples_transformed_emitters(){
+        let quad: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0)))
+        ));
+        let transform = Mat4::translation(Vec3::new(0.5, 0.0, 3.0))
+            *Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 0.6)
+            *Mat4::scaling(Vec3::new(1.5, 0.7, 1.0));
+        let instance = Instance::new(quad, transform).unwrap();
+        assert!(instance.is_emitter());
+        let origin = Vec3::new(0.2, -0.3, 0.0);
+        let total = check_sampling(|d| instance.pdf_value(&origin, d), |rng| instance.random(&origin, rng));
+        //The edges of the quad cut through bins, which the midpoint rule only approximates.
+        assert!((total-1.0).abs() < 1e-2, "{}", total);
+    }
+}
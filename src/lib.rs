@@ -0,0 +1,35 @@
+use crate::vec3::{Vec3, unit_vector};
+use crate::ray::Ray;
+use crate::hittable::Hittable;
+use crate::scene::Scene;
+pub mod vec3;
+pub mod ray;
+pub mod hittable;
+pub mod scene;
+pub mod utils;
+pub mod camera;
+pub mod material;
+pub mod aabb;
+pub mod bvh;
+pub mod image;
+pub mod output;
+pub mod scene_file;
+pub mod background;
+pub mod triangle;
+pub mod obj;
+pub mod sampler;
+pub mod texture;
+pub mod input;
+pub mod noise;
+pub mod onb;
+pub mod quad;
+pub mod matrix;
+pub mod instance;
+pub mod motion;
+pub mod pdf;
+pub mod light;
+pub mod environment;
+pub mod sky;
+pub mod medium;
+pub mod grid;
+pub mod microfacet;
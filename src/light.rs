@@ -0,0 +1,65 @@
+use crate::{
+    utils::{degrees_to_radians, INFINITY},
+    vec3::{dot, unit_vector},
+    Vec3
+};
+
+///Light arriving at a point from one light, before checking whether anything blocks it.
+pub struct LightSample{
+    ///Unit direction from the point toward the light.
+    pub direction: Vec3,
+    ///Distance to the light, infinite for directional lights.
+    pub distance: f64,
+    ///Irradiance the light delivers to a surface facing it.
+    pub irradiance: Vec3
+}
+
+///Lights without a surface, which rays can never hit and which are only found by shadow rays.
+pub enum Light{
+    ///Shines equally in every direction, with intensity falling off with the squared distance.
+    Point{position: Vec3, intensity: Vec3},
+    ///A point light restricted to a cone of half-angle cone_angle degrees around direction. Over the
+    ///last falloff degrees toward the edge its intensity fades smoothly to zero.
+    Spot{position: Vec3, direction: Vec3, intensity: Vec3, cone_angle: f64, falloff: f64},
+    ///Parallel light traveling along direction, like the sun, with the same irradiance everywhere.
+    Directional{direction: Vec3, irradiance: Vec3}
+}
+impl Light{
+    ///Returns the light reaching p, or None if p is outside a spotlight's cone.
+    pub fn illuminate(&self, p: &Vec3) -> Option<LightSample>{
+        match self{
+            Light::Point{position, intensity} => Some(point_sample(p, position, *intensity)),
+            Light::Spot{position, direction, intensity, cone_angle, falloff} => {
+                let sample = point_sample(p, position, *intensity);
+                let cos_theta = dot(&-sample.direction, &unit_vector(direction));
+                let cos_outer = f64::cos(degrees_to_radians(*cone_angle));
+                let cos_inner = f64::cos(degrees_to_radians(f64::max(cone_angle-falloff, 0.0)));
+                let scale = smoothstep(cos_outer, cos_inner, cos_theta);
+                if scale <= 0.0{
+                    return None;
+                }
+                Some(LightSample{irradiance: sample.irradiance*scale, ..sample})
+            }
+            Light::Directional{direction, irradiance} => Some(LightSample{
+                direction: -unit_vector(direction),
+                distance: INFINITY,
+                irradiance: *irradiance
+            })
+        }
+    }
+}
+
+fn point_sample(p: &Vec3, position: &Vec3, intensity: Vec3) -> LightSample{
+    let offset = *position-*p;
+    let distance = offset.length();
+    LightSample{direction: offset/distance, distance, irradiance: intensity/(distance*distance)}
+}
+
+///Rises smoothly from 0 at edge0 to 1 at edge1, stepping sharply if they are equal.
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64{
+    if edge1 <= edge0{
+        return if x >= edge0 {1.0} else {0.0};
+    }
+    let t = f64::clamp((x-edge0)/(edge1-edge0), 0.0, 1.0);
+    t*t*(3.0-2.0*t)
+}
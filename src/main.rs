@@ -1,53 +1,118 @@
-use material::Dielectric;
-use material::{Lambertian, Metal};
-use utils::PI;
-use vec3::{unit_vector, random_vec, random_vec_range};
-use crate::vec3::Vec3;
-use crate::ray::Ray;
-use crate::hittable::Sphere;
-use crate::hittable::Hittable;
-use crate::scene::Scene;
-use crate::camera::Camera;
+use rust_tracer_in_one_weekend::material::{Dielectric, Lambertian, Metal};
+use rust_tracer_in_one_weekend::vec3::{Vec3, random_vec, random_vec_range};
+use rust_tracer_in_one_weekend::hittable::Sphere;
+use rust_tracer_in_one_weekend::quad::Plane;
+use rust_tracer_in_one_weekend::scene::Scene;
+use rust_tracer_in_one_weekend::camera::CameraSettings;
+use rust_tracer_in_one_weekend::scene_file::load_scene;
+use rust_tracer_in_one_weekend::output::{save, write_ppm, PngDepth};
+use clap::Parser;
+use rust_tracer_in_one_weekend::sampler::Sampler;
+use rand::{Rng, SeedableRng};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use rand::{random, Rng};
-mod vec3;
-mod ray;
-mod hittable;
-mod scene;
-mod utils;
-mod camera;
-mod material;
 
-fn main() {
-    let aspect_ratio = 16.0/9.0;
-
-    let image_width = 1200;
+///Renders a scene file or one of the built-in scenes.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args{
+    ///Scene file (.toml) or built-in scene name: "full" or "simple".
+    #[arg(default_value = "full")]
+    scene: String,
+    ///Output file; the format is chosen from the extension (ppm, png, pfm, hdr, exr).
+    ///Writes PPM to stdout when omitted.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    ///Image width in pixels.
+    #[arg(long)]
+    width: Option<i32>,
+    ///Image height in pixels; defaults to the width divided by the scene's aspect ratio.
+    #[arg(long)]
+    height: Option<i32>,
+    ///Samples per pixel.
+    #[arg(short, long)]
+    spp: Option<i32>,
+    ///Maximum number of bounces per path.
+    #[arg(long)]
+    max_depth: Option<i32>,
+    ///Seed for all random numbers, including the sphere layout of the built-in full scene.
+    ///Renders with the same seed are identical regardless of thread count.
+    #[arg(long)]
+    seed: Option<u64>,
+    ///Number of render threads; defaults to the number of cores.
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+    ///Write 16 bits per channel instead of 8 when saving PNG files.
+    #[arg(long)]
+    png16: bool
+}
 
-    let vfov = 20.0;
-    let look_from = Vec3::new(13.0, 2.0, 3.0);
-    let look_at = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
+fn main() {
+    let args = Args::parse();
 
-    let samples_per_pixel = 100;
-    let max_depth = 100;
+    if let Some(output) = &args.output{
+        let supported = ["ppm", "png", "pfm", "hdr", "exr"];
+        let extension = output.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        if !extension.is_some_and(|e| supported.contains(&e.as_str())){
+            fail(&format!("unsupported output format: {}", output.display()));
+        }
+    }
+    if let Some(threads) = args.threads{
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()
+            .unwrap_or_else(|e| fail(&e.to_string()));
+    }
 
-    let defocus_angle = 0.1;
-    let focus_dist = 10.0;
+    let seed = args.seed.unwrap_or(CameraSettings::default().seed);
+    let (scene, mut settings) = match args.scene.as_str(){
+        "full" => (generate_full_scene(seed), CameraSettings::default()),
+        "simple" => (generate_simple_scene(), simple_scene_camera()),
+        path => load_scene(Path::new(path)).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+    };
 
-    let scene = generate_full_scene();
+    if let Some(width) = args.width{
+        settings.image_width = width;
+    }
+    if let Some(height) = args.height{
+        settings.image_height = Some(height);
+    }
+    if let Some(spp) = args.spp{
+        settings.samples_per_pixel = spp;
+    }
+    if let Some(max_depth) = args.max_depth{
+        settings.max_depth = max_depth;
+    }
+    if let Some(seed) = args.seed{
+        settings.seed = seed;
+    }
+    if settings.image_width <= 0 || settings.image_height.is_some_and(|height| height <= 0)
+        || settings.samples_per_pixel <= 0 || settings.max_depth <= 0{
+        fail("width, height, samples per pixel and max depth must be positive");
+    }
 
-    let camera = Camera::new(
-        aspect_ratio, 
-        image_width, 
-        vfov, 
-        look_from, 
-        look_at,
-        vup,
-        defocus_angle,
-        focus_dist,
-        samples_per_pixel,
-        max_depth);
-    camera.render(&scene);
+    let camera = settings.build();
+    let image = camera.render(&scene);
+    let png_depth = if args.png16 {PngDepth::Sixteen} else {PngDepth::Eight};
+    let result = match &args.output{
+        Some(path) => save(&image, path, png_depth),
+        None => write_ppm(&image, &mut BufWriter::new(io::stdout().lock()))
+    };
+    result.unwrap_or_else(|e| fail(&format!("failed to write image: {}", e)));
+}
+fn fail(message: &str) -> !{
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+fn simple_scene_camera() -> CameraSettings{
+    CameraSettings{
+        image_width: 400,
+        look_from: Vec3::new(-2.0, 2.0, 1.0),
+        look_at: Vec3::new(0.0, 0.0, -1.0),
+        defocus_angle: 0.0,
+        focus_dist: 3.4,
+        max_depth: 50,
+        ..CameraSettings::default()
+    }
 }
 fn generate_simple_scene() -> Scene{
     let mut scene = Scene::new();
@@ -86,31 +151,33 @@ fn generate_simple_scene() -> Scene{
     scene.push(Box::new(s2));
     scene.push(Box::new(s2_bubble));
     scene.push(Box::new(s3));
-    
+    scene.build_bvh();
+
     scene
 }
-fn generate_full_scene() -> Scene{
+fn generate_full_scene(seed: u64) -> Scene{
+    let mut rng = Sampler::seed_from_u64(seed);
     let mut scene = Scene::new();
     let material_ground = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
-    let ground: Sphere = Sphere::new(
-        Vec3::new(0.0, -1000.0, -0.0),
-        1000.0,
+    let ground = Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
         Arc::new(material_ground)
     );
     scene.push(Box::new(ground));
 
     for a in -11..11{
         for b in -11..11{
-            let choose_mat = rand::random::<f64>();
+            let choose_mat = rng.gen::<f64>();
             let center = Vec3::new(
-                a as f64+0.9*rand::random::<f64>(),
+                a as f64+0.9*rng.gen::<f64>(),
                 0.2,
-                b as f64+0.9*rand::random::<f64>()
+                b as f64+0.9*rng.gen::<f64>()
             );
 
             match choose_mat{
                 x if x < 0.8 => {
-                    let albedo = random_vec()*random_vec();
+                    let albedo = random_vec(&mut rng)*random_vec(&mut rng);
                     let sphere_material = Lambertian::new(albedo);
                     let sphere = Sphere::new(
                         center,
@@ -120,8 +187,8 @@ fn generate_full_scene() -> Scene{
                     scene.push(Box::new(sphere));
                 }
                 x if x < 0.95 => {
-                    let albedo = random_vec_range(0.5, 1.0);
-                    let fuzz = rand::random::<f64>()/2.0;
+                    let albedo = random_vec_range(0.5, 1.0, &mut rng);
+                    let fuzz = rng.gen::<f64>()/2.0;
                     let sphere_material = Metal::new(albedo, fuzz);
                     let sphere = Sphere::new(
                         center,
@@ -164,6 +231,7 @@ fn generate_full_scene() -> Scene{
     scene.push(Box::new(s1));
     scene.push(Box::new(s2));
     scene.push(Box::new(s3));
-    
+    scene.build_bvh();
+
     scene
-}
\ No newline at end of file
+}
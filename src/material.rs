@@ -1,62 +1,185 @@
-use crate::{hittable::HitRecord, ray::Ray, vec3::{dot, random_unit_vector, reflect, refract, unit_vector, Vec3}};
+use std::sync::Arc;
+use rand::Rng;
+use crate::{
+    hittable::HitRecord,
+    microfacet::{fresnel_conductor, metal_ior, Ggx},
+    onb::Onb,
+    pdf::{CosinePdf, GgxPdf, HenyeyGreensteinPdf, Pdf, SpherePdf},
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    utils::PI,
+    vec3::{dot, random_unit_vector, reflect, refract, unit_vector, Vec3}
+};
 
-pub trait Material{
-    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> Option<(Ray, Vec3)>;
+///How a material sends light onward from a hit.
+pub enum ScatterKind{
+    ///A single direction chosen by the material, such as a mirror reflection, which cannot be importance sampled.
+    Specular(Ray),
+    ///Directions drawn from a density, whose contribution is weighted by scattering_pdf.
+    Sampled(Box<dyn Pdf>)
+}
+
+pub struct ScatterRecord{
+    pub attenuation: Vec3,
+    pub kind: ScatterKind
+}
+
+pub trait Material: Send + Sync{
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord>;
+    ///Returns the density over solid angle with which the material scatters r_in into scattered.
+    ///Only used by materials that scatter with ScatterKind::Sampled.
+    fn scattering_pdf(&self, _r_in: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64{
+        0.0
+    }
+    ///Returns a color scaling the attenuation for light scattered from r_in into scattered, for materials whose color
+    ///depends on the directions, such as metals whose reflectance changes with angle.
+    fn scattering_tint(&self, _r_in: &Ray, _record: &HitRecord, _scattered: &Ray) -> Vec3{
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+    ///Returns the light given off at the hit point; black for materials that do not glow.
+    fn emitted(&self, _record: &HitRecord) -> Vec3{
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+    ///Returns true if emitted can be non-black, so objects made of this material are sampled as emitters.
+    fn is_emissive(&self) -> bool{
+        false
+    }
+    ///Returns how much of each color is absorbed per unit distance inside objects of this material,
+    ///for materials that rays pass into.
+    fn absorption(&self) -> Vec3{
+        Vec3::new(0.0, 0.0, 0.0)
+    }
 }
 pub struct Lambertian{
-    albedo: Vec3
+    texture: Arc<dyn Texture>
 }
 impl Lambertian{
     pub fn new(albedo: Vec3) -> Lambertian{
-        Lambertian{albedo}
+        Lambertian{texture: Arc::new(SolidColor::new(albedo))}
+    }
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Lambertian{
+        Lambertian{texture}
     }
 }
 impl Material for Lambertian{
-    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> Option<(Ray, Vec3)> {
-        let scatter_bounce: Vec3 = random_unit_vector();
-        let scatter_direction: Vec3 = if !(record.normal + scatter_bounce).near_zero(){
-            record.normal + scatter_bounce
-        }else{
-            record.normal
-        };
-        let scattered = Ray::new(record.p, scatter_direction);
-        let attenuation = self.albedo;
-        Some((scattered, attenuation))
+    fn scatter(&self, _r_in: &Ray, record: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord> {
+        let attenuation = self.texture.value(record.u, record.v, &record.p);
+        Some(ScatterRecord{attenuation, kind: ScatterKind::Sampled(Box::new(CosinePdf::new(&record.normal)))})
+    }
+    fn scattering_pdf(&self, _r_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64{
+        let cosine = dot(&record.normal, &unit_vector(&scattered.direction));
+        f64::max(cosine/PI, 0.0)
     }
 }
 
 pub struct Metal{
-    albedo: Vec3,
+    texture: Arc<dyn Texture>,
     fuzz: f64
 }
 impl Metal{
     pub fn new(albedo: Vec3, fuzz: f64) -> Metal{
+        Metal::from_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+    pub fn from_texture(texture: Arc<dyn Texture>, fuzz: f64) -> Metal{
         if fuzz > 1.0 || fuzz < 0.0{
             panic!("Fuzz must be between 0 and 1.")
         }
-        Metal{albedo, fuzz}
+        Metal{texture, fuzz}
     }
 }
 impl Material for Metal{
-    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> Option<(Ray, Vec3)> {
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord> {
         let reflected = reflect(&r_in.direction, &record.normal);
-        let scattered_direction = reflected + random_unit_vector() * self.fuzz;
-        let scattered = Ray::new(record.p, scattered_direction);
-        let attenuation = self.albedo;
+        let scattered_direction = reflected + random_unit_vector(rng) * self.fuzz;
+        let scattered = Ray::new(record.p, scattered_direction, r_in.time);
+        let attenuation = self.texture.value(record.u, record.v, &record.p);
         if dot(&scattered_direction, &record.normal) > 0.0 {
-            return Some((scattered, attenuation))
+            return Some(ScatterRecord{attenuation, kind: ScatterKind::Specular(scattered)})
         } else {
             return None
         }
     }
 }
 
+///A metal whose surface is made of microfacets following the GGX distribution, so that it reflects from a mirror
+///finish up to a broad sheen depending on roughness. Its color at each angle comes from its complex index of
+///refraction eta + ik. Anisotropic metals are rougher along the surface direction closest to the y axis, like
+///metal brushed around that axis.
+pub struct Conductor{
+    eta: Vec3,
+    k: Vec3,
+    distribution: Ggx
+}
+impl Conductor{
+    ///Returns a conductor whose roughness and anisotropy are each between 0 and 1.
+    pub fn new(eta: Vec3, k: Vec3, roughness: f64, anisotropy: f64) -> Conductor{
+        if !(0.0..=1.0).contains(&roughness) || !(0.0..=1.0).contains(&anisotropy){
+            panic!("Roughness and anisotropy must be between 0 and 1.")
+        }
+        //Squaring roughness makes it change evenly to the eye; anisotropy keeps the area of highlights the same.
+        let aspect = f64::sqrt(1.0-0.9*anisotropy);
+        let alpha = roughness*roughness;
+        Conductor{eta, k, distribution: Ggx::new(alpha/aspect, alpha*aspect)}
+    }
+    ///Returns a conductor made of gold, copper, aluminium or silver, or None for other metals.
+    pub fn preset(metal: &str, roughness: f64, anisotropy: f64) -> Option<Conductor>{
+        let (eta, k) = metal_ior(metal)?;
+        Some(Conductor::new(eta, k, roughness, anisotropy))
+    }
+    ///Returns the basis around normal whose u axis is the direction of alpha_x.
+    fn basis(normal: &Vec3) -> Onb{
+        Onb::aligned(normal, &Vec3::new(0.0, 1.0, 0.0))
+    }
+    ///Returns true for surfaces so smooth that they are treated as perfect mirrors.
+    fn is_smooth(&self) -> bool{
+        f64::max(self.distribution.alpha_x, self.distribution.alpha_y) < 1e-3
+    }
+}
+impl Material for Conductor{
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord> {
+        let outgoing = -unit_vector(&r_in.direction);
+        let cos_theta = dot(&outgoing, &record.normal);
+        if cos_theta <= 0.0{
+            return None;
+        }
+        if self.is_smooth(){
+            let scattered = Ray::new(record.p, reflect(&-outgoing, &record.normal), r_in.time);
+            let attenuation = fresnel_conductor(cos_theta, &self.eta, &self.k);
+            return Some(ScatterRecord{attenuation, kind: ScatterKind::Specular(scattered)});
+        }
+        let pdf = GgxPdf::new(Conductor::basis(&record.normal), &outgoing, self.distribution);
+        Some(ScatterRecord{attenuation: Vec3::new(1.0, 1.0, 1.0), kind: ScatterKind::Sampled(Box::new(pdf))})
+    }
+    fn scattering_pdf(&self, r_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64{
+        let basis = Conductor::basis(&record.normal);
+        let outgoing = basis.local(&-unit_vector(&r_in.direction));
+        let incoming = basis.local(&unit_vector(&scattered.direction));
+        if outgoing.z <= 0.0 || incoming.z <= 0.0{
+            return 0.0;
+        }
+        //The reflectance times the cosine to the normal, apart from the Fresnel term in scattering_tint.
+        let h = unit_vector(&(outgoing+incoming));
+        self.distribution.d(&h)*self.distribution.g2(&outgoing, &incoming)/(4.0*outgoing.z)
+    }
+    fn scattering_tint(&self, r_in: &Ray, _record: &HitRecord, scattered: &Ray) -> Vec3{
+        let outgoing = -unit_vector(&r_in.direction);
+        let h = unit_vector(&(outgoing+unit_vector(&scattered.direction)));
+        fresnel_conductor(dot(&outgoing, &h), &self.eta, &self.k)
+    }
+}
+
 pub struct Dielectric{
-    refraction_index: f64
+    refraction_index: f64,
+    absorption: Vec3
 }
 impl Dielectric{
     pub fn new(refraction_index: f64) -> Dielectric{
-        Dielectric { refraction_index }
+        Dielectric::absorbing(refraction_index, Vec3::new(0.0, 0.0, 0.0))
+    }
+    ///Returns colored glass, which absorbs each color at its rate in absorption per unit distance traveled inside.
+    pub fn absorbing(refraction_index: f64, absorption: Vec3) -> Dielectric{
+        Dielectric{refraction_index, absorption}
     }
     pub fn reflectance(&self, cos_theta: f64) -> f64{
         let r0: f64 = (1.0-self.refraction_index)/(1.0+self.refraction_index);
@@ -65,21 +188,94 @@ impl Dielectric{
     }
 }
 impl Material for Dielectric{
-    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> Option<(Ray, Vec3)> {
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord> {
         let ri = if record.front_face {1.0/self.refraction_index} else {self.refraction_index};
         let unit_direction = unit_vector(&r_in.direction);
 
         let cos_theta = f64::min(dot(&-unit_direction, &record.normal), 1.0);
         let sin_theta = f64::sqrt(1.0-cos_theta*cos_theta);
 
-        let direction = if sin_theta * ri > 1.0 || self.reflectance(cos_theta) > rand::random(){
+        let direction = if sin_theta * ri > 1.0 || self.reflectance(cos_theta) > rng.gen::<f64>(){
             reflect(&unit_direction, &record.normal)
         } else {
             refract(&unit_direction, &record.normal, ri)
         };
 
-        let scattered = Ray::new(record.p, direction);
+        let scattered = Ray::new(record.p, direction, r_in.time);
         let attenuation = Vec3::new(1.0, 1.0, 1.0);
-        Some((scattered, attenuation))
+        Some(ScatterRecord{attenuation, kind: ScatterKind::Specular(scattered)})
+    }
+    fn absorption(&self) -> Vec3{
+        self.absorption
+    }
+}
+
+///A light-emitting surface that absorbs every ray hitting it and glows from its front face only.
+pub struct DiffuseLight{
+    emit: Vec3
+}
+impl DiffuseLight{
+    pub fn new(emit: Vec3) -> DiffuseLight{
+        DiffuseLight{emit}
+    }
+}
+impl Material for DiffuseLight{
+    fn scatter(&self, _r_in: &Ray, _record: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord> {
+        None
+    }
+    fn emitted(&self, record: &HitRecord) -> Vec3{
+        if record.front_face {self.emit} else {Vec3::new(0.0, 0.0, 0.0)}
+    }
+    fn is_emissive(&self) -> bool{
+        true
+    }
+}
+
+///A phase function for volumes that scatters light equally in every direction.
+pub struct Isotropic{
+    texture: Arc<dyn Texture>
+}
+impl Isotropic{
+    pub fn new(albedo: Vec3) -> Isotropic{
+        Isotropic{texture: Arc::new(SolidColor::new(albedo))}
     }
-}
\ No newline at end of file
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Isotropic{
+        Isotropic{texture}
+    }
+}
+impl Material for Isotropic{
+    fn scatter(&self, _r_in: &Ray, record: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord> {
+        let attenuation = self.texture.value(record.u, record.v, &record.p);
+        Some(ScatterRecord{attenuation, kind: ScatterKind::Sampled(Box::new(SpherePdf))})
+    }
+    fn scattering_pdf(&self, _r_in: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64{
+        1.0/(4.0*PI)
+    }
+}
+
+///A phase function for volumes that favors scattering forward (g > 0) or backward (g < 0), like haze or clouds.
+pub struct HenyeyGreenstein{
+    texture: Arc<dyn Texture>,
+    g: f64
+}
+impl HenyeyGreenstein{
+    pub fn new(albedo: Vec3, g: f64) -> HenyeyGreenstein{
+        HenyeyGreenstein::from_texture(Arc::new(SolidColor::new(albedo)), g)
+    }
+    pub fn from_texture(texture: Arc<dyn Texture>, g: f64) -> HenyeyGreenstein{
+        if g <= -1.0 || g >= 1.0{
+            panic!("g must be between -1 and 1.")
+        }
+        HenyeyGreenstein{texture, g}
+    }
+}
+impl Material for HenyeyGreenstein{
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord> {
+        let attenuation = self.texture.value(record.u, record.v, &record.p);
+        Some(ScatterRecord{attenuation, kind: ScatterKind::Sampled(Box::new(HenyeyGreensteinPdf::new(&r_in.direction, self.g)))})
+    }
+    fn scattering_pdf(&self, r_in: &Ray, _record: &HitRecord, scattered: &Ray) -> f64{
+        let cos_theta = dot(&unit_vector(&r_in.direction), &unit_vector(&scattered.direction));
+        HenyeyGreensteinPdf::phase(cos_theta, self.g)
+    }
+}
@@ -0,0 +1,185 @@
+use std::ops;
+use crate::vec3::{unit_vector, Vec3};
+
+///A 4x4 matrix in row-major order, acting on column vectors.
+#[derive(Copy, Clone)]
+pub struct Mat4{
+    pub m: [[f64; 4]; 4]
+}
+impl Mat4{
+    pub fn identity() -> Mat4{
+        Mat4::scaling(Vec3::new(1.0, 1.0, 1.0))
+    }
+    pub fn translation(offset: Vec3) -> Mat4{
+        Mat4{m: [
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0]
+        ]}
+    }
+    pub fn scaling(factors: Vec3) -> Mat4{
+        Mat4{m: [
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]}
+    }
+    ///Returns a rotation by angle radians counterclockwise around axis.
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4{
+        Quaternion::from_axis_angle(axis, angle).to_matrix()
+    }
+    pub fn transpose(&self) -> Mat4{
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate(){
+            for (j, value) in row.iter_mut().enumerate(){
+                *value = self.m[j][i];
+            }
+        }
+        Mat4{m}
+    }
+    ///Returns the inverse found by Gauss-Jordan elimination, or None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat4>{
+        let mut a = self.m;
+        let mut inverse = Mat4::identity().m;
+        for column in 0..4{
+            let pivot = (column..4).max_by(|&i, &j| f64::abs(a[i][column]).total_cmp(&f64::abs(a[j][column])))?;
+            if f64::abs(a[pivot][column]) < 1e-12{
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0/a[column][column];
+            for k in 0..4{
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+            for row in 0..4{
+                if row != column{
+                    let factor = a[row][column];
+                    for k in 0..4{
+                        a[row][k] -= factor*a[column][k];
+                        inverse[row][k] -= factor*inverse[column][k];
+                    }
+                }
+            }
+        }
+        Some(Mat4{m: inverse})
+    }
+    ///Returns the determinant of the upper-left 3x3 block, by which the transform scales volumes.
+    pub fn linear_determinant(&self) -> f64{
+        let m = &self.m;
+        m[0][0]*(m[1][1]*m[2][2] - m[1][2]*m[2][1])
+            - m[0][1]*(m[1][0]*m[2][2] - m[1][2]*m[2][0])
+            + m[0][2]*(m[1][0]*m[2][1] - m[1][1]*m[2][0])
+    }
+    ///Transforms a point, applying translation.
+    pub fn transform_point(&self, p: &Vec3) -> Vec3{
+        let m = &self.m;
+        let x = m[0][0]*p.x + m[0][1]*p.y + m[0][2]*p.z + m[0][3];
+        let y = m[1][0]*p.x + m[1][1]*p.y + m[1][2]*p.z + m[1][3];
+        let z = m[2][0]*p.x + m[2][1]*p.y + m[2][2]*p.z + m[2][3];
+        let w = m[3][0]*p.x + m[3][1]*p.y + m[3][2]*p.z + m[3][3];
+        if w == 1.0 {Vec3::new(x, y, z)} else {Vec3::new(x, y, z)/w}
+    }
+    ///Transforms a direction, ignoring translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3{
+        let m = &self.m;
+        Vec3::new(
+            m[0][0]*v.x + m[0][1]*v.y + m[0][2]*v.z,
+            m[1][0]*v.x + m[1][1]*v.y + m[1][2]*v.z,
+            m[2][0]*v.x + m[2][1]*v.y + m[2][2]*v.z
+        )
+    }
+}
+impl ops::Mul<Mat4> for Mat4{
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Self::Output{
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate(){
+            for (j, value) in row.iter_mut().enumerate(){
+                *value = (0..4).map(|k| self.m[i][k]*rhs.m[k][j]).sum();
+            }
+        }
+        Mat4{m}
+    }
+}
+
+///A quaternion w + xi + yj + zk. Unit quaternions represent rotations.
+#[derive(Copy, Clone)]
+pub struct Quaternion{
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+impl Quaternion{
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion{
+        Quaternion{w, x, y, z}
+    }
+    ///Returns the rotation by angle radians counterclockwise around axis.
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Quaternion{
+        let axis = unit_vector(&axis);
+        let (s, c) = f64::sin_cos(angle/2.0);
+        Quaternion::new(c, axis.x*s, axis.y*s, axis.z*s)
+    }
+    ///Returns the rotation by the given angles in radians around x, then y, then z.
+    pub fn from_euler(angles: Vec3) -> Quaternion{
+        Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), angles.z)
+            * Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angles.y)
+            * Quaternion::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), angles.x)
+    }
+    pub fn dot(&self, other: &Quaternion) -> f64{
+        self.w*other.w + self.x*other.x + self.y*other.y + self.z*other.z
+    }
+    pub fn normalized(&self) -> Quaternion{
+        let length = f64::sqrt(self.dot(self));
+        Quaternion::new(self.w/length, self.x/length, self.y/length, self.z/length)
+    }
+    ///Spherically interpolates between two rotations along the shorter arc.
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion{
+        let mut cos_theta = self.dot(other);
+        let mut other = *other;
+        if cos_theta < 0.0{
+            other = Quaternion::new(-other.w, -other.x, -other.y, -other.z);
+            cos_theta = -cos_theta;
+        }
+        //Nearly parallel rotations are interpolated linearly to avoid dividing by sin(theta) ~ 0.
+        let (a, b) = if cos_theta > 0.9995{
+            (1.0-t, t)
+        }else{
+            let theta = f64::acos(cos_theta);
+            let sin_theta = f64::sin(theta);
+            (f64::sin((1.0-t)*theta)/sin_theta, f64::sin(t*theta)/sin_theta)
+        };
+        Quaternion::new(
+            a*self.w + b*other.w,
+            a*self.x + b*other.x,
+            a*self.y + b*other.y,
+            a*self.z + b*other.z
+        ).normalized()
+    }
+    ///Returns the rotation matrix of this quaternion, which is normalized first.
+    pub fn to_matrix(&self) -> Mat4{
+        let Quaternion{w, x, y, z} = self.normalized();
+        Mat4{m: [
+            [1.0-2.0*(y*y+z*z), 2.0*(x*y-w*z), 2.0*(x*z+w*y), 0.0],
+            [2.0*(x*y+w*z), 1.0-2.0*(x*x+z*z), 2.0*(y*z-w*x), 0.0],
+            [2.0*(x*z-w*y), 2.0*(y*z+w*x), 1.0-2.0*(x*x+y*y), 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]}
+    }
+}
+impl ops::Mul<Quaternion> for Quaternion{
+    type Output = Quaternion;
+    fn mul(self, rhs: Quaternion) -> Self::Output{
+        Quaternion::new(
+            self.w*rhs.w - self.x*rhs.x - self.y*rhs.y - self.z*rhs.z,
+            self.w*rhs.x + self.x*rhs.w + self.y*rhs.z - self.z*rhs.y,
+            self.w*rhs.y - self.x*rhs.z + self.y*rhs.w + self.z*rhs.x,
+            self.w*rhs.z + self.x*rhs.y - self.y*rhs.x + self.z*rhs.w
+        )
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use crate::{
+    aabb::Aabb,
+    grid::Grid,
+    hittable::HitRecord,
+    material::{Material, ScatterRecord},
+    sampler::{ray_random, Sampler},
+    utils::{Interval, UNIVERSE},
+    Hittable, Ray, Vec3
+};
+
+///A volume of constant density filling a closed boundary, such as fog or smoke. Rays passing through it
+///scatter at a random distance, more often the denser it is, using the phase function material.
+///The boundary must be convex, since only the first place a ray enters it and the next place it leaves are used.
+pub struct ConstantMedium{
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+    ///Mixed into the ray hash so that media crossed by the same ray scatter independently.
+    salt: u64
+}
+impl ConstantMedium{
+    ///Makes a medium whose random distances are drawn with salt, which should come from sampler::object_salt
+    ///and differ between the media of a scene.
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>, salt: u64) -> ConstantMedium{
+        ConstantMedium{boundary, neg_inv_density: -1.0/density, phase_function, salt}
+    }
+}
+impl ConstantMedium{
+    ///Returns the part of ray_t for which r is inside the boundary.
+    fn span(&self, r: &Ray, ray_t: &Interval) -> Option<Interval>{
+        //Finds where the whole line enters and leaves the boundary, so rays starting inside still see it.
+        let entry = self.boundary.intersect(r, &UNIVERSE)?;
+        let exit = self.boundary.intersect(r, &Interval::new(entry.t+0.0001, UNIVERSE.max))?;
+
+        let t_enter = f64::max(entry.t, f64::max(ray_t.min, 0.0));
+        let t_exit = f64::min(exit.t, ray_t.max);
+        if t_enter >= t_exit {None} else {Some(Interval::new(t_enter, t_exit))}
+    }
+}
+impl Hittable for ConstantMedium{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let span = self.span(r, ray_t)?;
+        let ray_length = r.direction.length();
+        let distance_inside = span.size()*ray_length;
+        //The distance to the next scattering event is exponentially distributed.
+        let hit_distance = self.neg_inv_density*f64::ln(1.0-ray_random(r, self.salt));
+        if hit_distance > distance_inside{
+            return None;
+        }
+
+        let t = span.min + hit_distance/ray_length;
+        //Volumes have no surface, so the normal and face are arbitrary.
+        Some(HitRecord::new(r.at(t), Vec3::new(1.0, 0.0, 0.0), t, 0.0, 0.0, true, self.phase_function.clone()))
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.boundary.bounding_box()
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        match self.span(r, ray_t){
+            Some(span) => f64::exp(span.size()*r.direction.length()/self.neg_inv_density),
+            None => 1.0
+        }
+    }
+}
+
+///A volume whose density and emission vary over a voxel grid stretched across an axis-aligned box, such as a cloud
+///or an explosion. Rays pass through it by delta tracking and shadow rays by ratio tracking, which both fill the grid
+///up to its largest density with fictitious matter that does not scatter, so that collisions are easy to sample.
+///Collisions are located in the grid by their u, v and w coordinates.
+pub struct GridMedium{
+    grid: Arc<Grid>,
+    bbox: Aabb,
+    density_scale: f64,
+    ///The largest density in the grid, which bounds the density everywhere in it.
+    majorant: f64,
+    ///The phase function, wrapped in a Glow if the grid has emission.
+    material: Arc<dyn Material>,
+    ///Mixed into the ray hash so that media crossed by the same ray collide independently.
+    salt: u64
+}
+impl GridMedium{
+    ///Stretches grid across the box between corners a and b. Its densities are multiplied by density_scale and its
+    ///emission, which is the light given off per collision, by emission_scale. Salt is as for ConstantMedium::new.
+    pub fn new(grid: Grid, a: Vec3, b: Vec3, density_scale: f64, emission_scale: f64, phase_function: Arc<dyn Material>, salt: u64) -> GridMedium{
+        let majorant = grid.max_density()*density_scale;
+        let grid = Arc::new(grid);
+        let material: Arc<dyn Material> = if grid.has_emission(){
+            Arc::new(Glow{phase_function, grid: grid.clone(), emission_scale})
+        }else{
+            phase_function
+        };
+        GridMedium{grid, bbox: Aabb::from_points(a, b), density_scale, majorant, material, salt}
+    }
+    ///Returns the position of p within the box, from 0 to 1 along each axis.
+    fn local(&self, p: &Vec3) -> Vec3{
+        Vec3::new(
+            (p.x-self.bbox.x.min)/self.bbox.x.size(),
+            (p.y-self.bbox.y.min)/self.bbox.y.size(),
+            (p.z-self.bbox.z.min)/self.bbox.z.size()
+        )
+    }
+    fn density(&self, p: &Vec3) -> f64{
+        self.grid.density(&self.local(p))*self.density_scale
+    }
+    ///Returns the t of the next tentative collision after t, drawn against the majorant.
+    fn step(&self, r: &Ray, t: f64, salt: u64) -> f64{
+        t - f64::ln(1.0-ray_random(r, self.salt+salt))/(self.majorant*r.direction.length())
+    }
+}
+impl Hittable for GridMedium{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        if self.majorant <= 0.0{
+            return None;
+        }
+        let span = self.bbox.clip(r, ray_t)?;
+        let mut t = span.min;
+        let mut salt = 0;
+        loop{
+            t = self.step(r, t, salt);
+            if t >= span.max{
+                return None;
+            }
+            let p = r.at(t);
+            let local = self.local(&p);
+            //Collisions are real in proportion to the real density, and otherwise with fictitious matter.
+            if ray_random(r, self.salt+salt+1)*self.majorant < self.grid.density(&local)*self.density_scale{
+                let record = HitRecord::new(p, Vec3::new(1.0, 0.0, 0.0), t, local.x, local.y, true, self.material.clone());
+                return Some(HitRecord{w: local.z, ..record});
+            }
+            salt += 2;
+        }
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        let span = match self.bbox.clip(r, ray_t){
+            Some(span) if self.majorant > 0.0 => span,
+            _ => return 1.0
+        };
+        //Each tentative collision passes on the fraction of it that is fictitious.
+        let mut transmittance = 1.0;
+        let mut t = span.min;
+        let mut salt = 0;
+        loop{
+            t = self.step(r, t, salt);
+            if t >= span.max{
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(&r.at(t))/self.majorant;
+            salt += 1;
+        }
+    }
+}
+
+///The phase function of a glowing grid, which also gives off the grid's emission at the collision's u, v and w.
+///It is not an emitter, since volumes cannot be sampled like surfaces; paths only pick up its light by colliding.
+struct Glow{
+    phase_function: Arc<dyn Material>,
+    grid: Arc<Grid>,
+    emission_scale: f64
+}
+impl Material for Glow{
+    fn scatter(&self, r_in: &Ray, record: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord>{
+        self.phase_function.scatter(r_in, record, rng)
+    }
+    fn scattering_pdf(&self, r_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64{
+        self.phase_function.scattering_pdf(r_in, record, scattered)
+    }
+    fn scattering_tint(&self, r_in: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3{
+        self.phase_function.scattering_tint(r_in, record, scattered)
+    }
+    fn emitted(&self, record: &HitRecord) -> Vec3{
+        self.grid.emission(&Vec3::new(record.u, record.v, record.w))*self.emission_scale
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use rand::{Rng, SeedableRng};
+    use crate::{instance::Instance, material::Isotropic, matrix::Mat4, quad::Cuboid, sampler::object_salt};
+    use super::*;
+
+    fn fog(density: f64, index: u64) -> ConstantMedium{
+        let white = Arc::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0)));
+        let boundary = Arc::new(Cuboid::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), white.clone()));
+        ConstantMedium::new(boundary, density, white, object_salt(0, index))
+    }
+    ///Returns rays crossing the unit box along z, each 2 long inside it.
+    fn rays(count: usize) -> Vec<Ray>{
+        let mut rng = Sampler::seed_from_u64(5);
+        (0..count).map(|_| Ray::new(
+            Vec3::new(rng.gen_range(-0.9..0.9), rng.gen_range(-0.9..0.9), -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0
+        )).collect()
+    }
+    fn ray_t() -> Interval{
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn scatters_at_the_expected_rate(){
+        let fog = fog(0.5, 0);
+        let rays = rays(20000);
+        let passed = rays.iter().filter(|r| fog.intersect(r, &ray_t()).is_none()).count() as f64/rays.len() as f64;
+        assert!((passed-f64::exp(-1.0)).abs() < 0.015, "{}", passed);
+        assert!((fog.transmittance(&rays[0], &ray_t())-f64::exp(-1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn overlapping_media_scatter_independently(){
+        let (a, b) = (fog(0.5, 0), fog(0.5, 1));
+        let rays = rays(20000);
+        let passed = rays.iter()
+            .filter(|r| a.intersect(r, &ray_t()).is_none() && b.intersect(r, &ray_t()).is_none())
+            .count() as f64/rays.len() as f64;
+        assert!((passed-f64::exp(-2.0)).abs() < 0.015, "{}", passed);
+    }
+
+    #[test]
+    fn rays_starting_inside_see_the_rest(){
+        let fog = fog(1.0, 0);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!((fog.transmittance(&r, &ray_t())-f64::exp(-0.5)).abs() < 1e-3);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert_eq!(fog.transmittance(&r, &ray_t()), 1.0);
+    }
+
+    ///A unit cube whose density rises from 0 below z = 1/4 to 2 above z = 3/4, and whose emission is
+    ///its position along z, so that a ray along z sees an optical depth of 1.
+    fn ramp(density_scale: f64) -> GridMedium{
+        let emission = vec![Vec3::new(0.25, 0.25, 0.25), Vec3::new(0.75, 0.75, 0.75)];
+        let grid = Grid::new([1, 1, 2], vec![0.0, 2.0], Some(emission));
+        let white = Arc::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0)));
+        GridMedium::new(grid, Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 0.0), density_scale, 1.0, white, object_salt(0, 0))
+    }
+
+    #[test]
+    fn grid_tracking_matches_the_optical_depth(){
+        let volume = ramp(1.0);
+        let rays = rays(20000);
+        let passed = rays.iter().filter(|r| volume.intersect(r, &ray_t()).is_none()).count() as f64/rays.len() as f64;
+        assert!((passed-f64::exp(-1.0)).abs() < 0.015, "{}", passed);
+        let transmittance = rays.iter().map(|r| volume.transmittance(r, &ray_t())).sum::<f64>()/rays.len() as f64;
+        assert!((transmittance-f64::exp(-1.0)).abs() < 0.01, "{}", transmittance);
+    }
+
+    #[test]
+    fn grid_emission_follows_instances(){
+        let volume: Arc<dyn Hittable> = Arc::new(ramp(50.0));
+        let instance = Instance::new(volume, Mat4::translation(Vec3::new(10.0, 0.0, 0.0))).unwrap();
+        for r in rays(100){
+            let r = Ray::new(r.origin + Vec3::new(10.0, 0.0, 0.0), r.direction, 0.0);
+            let record = instance.intersect(&r, &ray_t()).unwrap();
+            //The emission ramps from 0.25 to 0.75 between the same z as the density.
+            let z = (record.p.z+1.0).clamp(0.25, 0.75);
+            assert!((record.material.emitted(&record).y-z).abs() < 1e-9);
+        }
+    }
+}
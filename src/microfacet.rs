@@ -0,0 +1,169 @@
+//!Microfacet models of rough surfaces, which are made of tiny mirrors whose normals follow a distribution.
+//!
+//!Directions are given in a local frame whose z axis is the surface normal.
+use rand::Rng;
+use crate::{
+    sampler::Sampler,
+    utils::PI,
+    vec3::{cross, unit_vector, Vec3}
+};
+
+///The GGX (Trowbridge-Reitz) distribution of microfacet normals, which is rougher along x with a larger
+///alpha_x and along y with a larger alpha_y.
+#[derive(Copy, Clone)]
+pub struct Ggx{
+    pub alpha_x: f64,
+    pub alpha_y: f64
+}
+impl Ggx{
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx{
+        Ggx{alpha_x, alpha_y}
+    }
+    ///Returns the density of microfacets with normal h, per unit area of the surface.
+    pub fn d(&self, h: &Vec3) -> f64{
+        if h.z <= 0.0{
+            return 0.0;
+        }
+        let e = (h.x/self.alpha_x).powi(2) + (h.y/self.alpha_y).powi(2) + h.z*h.z;
+        1.0/(PI*self.alpha_x*self.alpha_y*e*e)
+    }
+    ///Returns Smith's lambda for w, the area of microfacets hidden from w relative to the visible area.
+    fn lambda(&self, w: &Vec3) -> f64{
+        let tan2 = ((self.alpha_x*w.x).powi(2) + (self.alpha_y*w.y).powi(2))/(w.z*w.z);
+        (f64::sqrt(1.0+tan2)-1.0)/2.0
+    }
+    ///Returns the fraction of microfacets facing w that are not shadowed from it.
+    pub fn g1(&self, w: &Vec3) -> f64{
+        1.0/(1.0+self.lambda(w))
+    }
+    ///Returns the fraction of microfacets visible from both wo and wi, counting microfacets low enough
+    ///to be hidden from both only once.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64{
+        1.0/(1.0+self.lambda(wo)+self.lambda(wi))
+    }
+    ///Returns a microfacet normal drawn in proportion to its area visible from wo, which must be above the surface.
+    ///This is Heitz's method of sampling the visible normals of a stretched hemisphere.
+    pub fn sample_visible_normal(&self, wo: &Vec3, rng: &mut Sampler) -> Vec3{
+        //Stretches the view so that the distribution becomes that of a hemisphere.
+        let vh = unit_vector(&Vec3::new(self.alpha_x*wo.x, self.alpha_y*wo.y, wo.z));
+        let length_squared = vh.x*vh.x + vh.y*vh.y;
+        let t1 = if length_squared > 0.0 {Vec3::new(-vh.y, vh.x, 0.0)/f64::sqrt(length_squared)} else {Vec3::new(1.0, 0.0, 0.0)};
+        let t2 = cross(&vh, &t1);
+
+        //Picks a point on the disk the hemisphere projects to, squashed by the part hidden behind it.
+        let r = f64::sqrt(rng.gen::<f64>());
+        let phi = 2.0*PI*rng.gen::<f64>();
+        let p1 = r*f64::cos(phi);
+        let s = 0.5*(1.0+vh.z);
+        let p2 = (1.0-s)*f64::sqrt(1.0-p1*p1) + s*r*f64::sin(phi);
+        let nh = t1*p1 + t2*p2 + vh*f64::sqrt(f64::max(0.0, 1.0-p1*p1-p2*p2));
+
+        //Unstretches the normal found on the hemisphere.
+        unit_vector(&Vec3::new(self.alpha_x*nh.x, self.alpha_y*nh.y, f64::max(0.0, nh.z)))
+    }
+    ///Returns the density over solid angle with which reflecting wo off a normal from sample_visible_normal gives wi.
+    pub fn reflection_pdf(&self, wo: &Vec3, wi: &Vec3) -> f64{
+        if wo.z <= 0.0 || wi.z <= 0.0{
+            return 0.0;
+        }
+        let h = unit_vector(&(*wo + *wi));
+        self.g1(wo)*self.d(&h)/(4.0*wo.z)
+    }
+}
+
+///Returns the fraction of unpolarized light reflected by a conductor with complex index of refraction eta + ik,
+///for light arriving at an angle to the normal with cosine cos_theta.
+pub fn fresnel_conductor(cos_theta: f64, eta: &Vec3, k: &Vec3) -> Vec3{
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0-cos2;
+    let channel = |eta: f64, k: f64| {
+        let t0 = eta*eta - k*k - sin2;
+        let a2b2 = f64::sqrt(t0*t0 + 4.0*eta*eta*k*k);
+        let a = f64::sqrt(0.5*(a2b2+t0));
+        let t1 = a2b2 + cos2;
+        let t2 = 2.0*f64::sqrt(cos2)*a;
+        let rs = (t1-t2)/(t1+t2);
+        let t3 = cos2*a2b2 + sin2*sin2;
+        let t4 = t2*sin2;
+        let rp = rs*(t3-t4)/(t3+t4);
+        (rs+rp)/2.0
+    };
+    Vec3::new(channel(eta.x, k.x), channel(eta.y, k.y), channel(eta.z, k.z))
+}
+
+///Returns the complex index of refraction (eta, k) of gold, copper, aluminium or silver, measured at red, green
+///and blue wavelengths, or None for other names.
+pub fn metal_ior(name: &str) -> Option<(Vec3, Vec3)>{
+    match name{
+        "gold" => Some((Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603))),
+        "copper" => Some((Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142))),
+        "aluminium" | "aluminum" => Some((Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837))),
+        "silver" => Some((Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147))),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::vec3::dot;
+    use super::*;
+
+    ///Integrates f over the upper hemisphere with the midpoint rule in (cos theta, phi).
+    fn integrate_hemisphere(f: impl Fn(&Vec3) -> f64) -> f64{
+        let steps = 1000;
+        let (cos_step, phi_step) = (1.0/steps as f64, 2.0*PI/(2*steps) as f64);
+        let mut sum = 0.0;
+        for a in 0..steps{
+            let cos_theta = (a as f64+0.5)*cos_step;
+            let sin_theta = f64::sqrt(1.0-cos_theta*cos_theta);
+            for b in 0..2*steps{
+                let phi = (b as f64+0.5)*phi_step;
+                sum += f(&Vec3::new(sin_theta*f64::cos(phi), sin_theta*f64::sin(phi), cos_theta))*cos_step*phi_step;
+            }
+        }
+        sum
+    }
+    fn distributions() -> [Ggx; 3]{
+        [Ggx::new(0.5, 0.5), Ggx::new(0.3, 0.7), Ggx::new(0.9, 0.9)]
+    }
+
+    #[test]
+    fn microfacets_cover_the_surface(){
+        for ggx in distributions(){
+            assert!((integrate_hemisphere(|h| ggx.d(h)*h.z)-1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn visible_microfacets_cover_the_projected_surface(){
+        let wo = unit_vector(&Vec3::new(0.5, -0.3, 0.6));
+        for ggx in distributions(){
+            let visible = integrate_hemisphere(|h| ggx.g1(&wo)*f64::max(dot(&wo, h), 0.0)*ggx.d(h)/wo.z);
+            assert!((visible-1.0).abs() < 1e-3, "{}", visible);
+        }
+    }
+
+    #[test]
+    fn masking_is_a_fraction(){
+        let (wo, wi) = (unit_vector(&Vec3::new(0.9, 0.0, 0.2)), unit_vector(&Vec3::new(-0.3, 0.4, 0.8)));
+        for ggx in distributions(){
+            let g2 = ggx.g2(&wo, &wi);
+            assert!(g2 > 0.0 && g2 <= ggx.g1(&wo) && g2 <= ggx.g1(&wi));
+            assert!((ggx.g1(&Vec3::new(0.0, 0.0, 1.0))-1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn conductor_fresnel(){
+        let (eta, k) = metal_ior("gold").unwrap();
+        let normal = fresnel_conductor(1.0, &eta, &k);
+        let expected = |eta: f64, k: f64| ((eta-1.0).powi(2) + k*k)/((eta+1.0).powi(2) + k*k);
+        assert!((normal.x-expected(eta.x, k.x)).abs() < 1e-9);
+        assert!((normal.z-expected(eta.z, k.z)).abs() < 1e-9);
+        //Gold reflects more red than blue, and everything at grazing angles.
+        assert!(normal.x > normal.z);
+        let grazing = fresnel_conductor(0.0, &eta, &k);
+        assert!((grazing.x-1.0).abs() < 1e-9 && (grazing.z-1.0).abs() < 1e-9);
+        assert!(metal_ior("aluminum").is_some() && metal_ior("tin").is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    aabb::{Aabb, EMPTY_BOX},
+    matrix::{Mat4, Quaternion},
+    vec3::Vec3
+};
+
+///Values that can be blended between keyframes, with t running from 0 at self to 1 at other.
+pub trait Interpolate: Copy{
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+impl Interpolate for Vec3{
+    fn interpolate(&self, other: &Vec3, t: f64) -> Vec3{
+        *self*(1.0-t) + *other*t
+    }
+}
+
+///A value animated over time by interpolating between keyframes. Before the first and after the
+///last keyframe the value holds still.
+#[derive(Clone)]
+pub struct Keyframes<T: Interpolate>{
+    keys: Vec<(f64, T)>
+}
+impl<T: Interpolate> Keyframes<T>{
+    ///Returns None if there are no keys. Keys are sorted by time.
+    pub fn new(mut keys: Vec<(f64, T)>) -> Option<Keyframes<T>>{
+        if keys.is_empty(){
+            return None;
+        }
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(Keyframes{keys})
+    }
+    pub fn constant(value: T) -> Keyframes<T>{
+        Keyframes{keys: vec![(0.0, value)]}
+    }
+    ///Moves from start at time 0 to end at time 1.
+    pub fn linear(start: T, end: T) -> Keyframes<T>{
+        Keyframes{keys: vec![(0.0, start), (1.0, end)]}
+    }
+    pub fn is_constant(&self) -> bool{
+        self.keys.len() == 1
+    }
+    pub fn at(&self, time: f64) -> T{
+        //The index of the first key later than time.
+        let next = self.keys.partition_point(|(t, _)| *t <= time);
+        if next == 0{
+            return self.keys[0].1;
+        }
+        if next == self.keys.len(){
+            return self.keys[next-1].1;
+        }
+        let (t0, a) = &self.keys[next-1];
+        let (t1, b) = &self.keys[next];
+        a.interpolate(b, (time-t0)/(t1-t0))
+    }
+    pub fn keys(&self) -> &[(f64, T)]{
+        &self.keys
+    }
+}
+
+///A scale, then a rotation, then a translation.
+#[derive(Copy, Clone)]
+pub struct Pose{
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3
+}
+impl Pose{
+    pub fn matrix(&self) -> Mat4{
+        Mat4::translation(self.translation) * self.rotation.to_matrix() * Mat4::scaling(self.scale)
+    }
+    ///Returns the inverse of matrix, built directly from the inverted parts.
+    pub fn inverse_matrix(&self) -> Mat4{
+        let inverse_scale = Vec3::new(1.0/self.scale.x, 1.0/self.scale.y, 1.0/self.scale.z);
+        Mat4::scaling(inverse_scale) * self.rotation.to_matrix().transpose() * Mat4::translation(-self.translation)
+    }
+    pub fn is_invertible(&self) -> bool{
+        self.scale.x != 0.0 && self.scale.y != 0.0 && self.scale.z != 0.0
+    }
+}
+impl Interpolate for Pose{
+    fn interpolate(&self, other: &Pose, t: f64) -> Pose{
+        Pose{
+            translation: self.translation.interpolate(&other.translation, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: self.scale.interpolate(&other.scale, t)
+        }
+    }
+}
+
+///Returns a box enclosing bbox transformed by every pose along the animation.
+pub(crate) fn animated_box(bbox: &Aabb, poses: &Keyframes<Pose>) -> Aabb{
+    let keys = poses.keys();
+    let mut result = keys.iter().fold(EMPTY_BOX, |b, (_, pose)| Aabb::surrounding(&b, &transform_box(bbox, &pose.matrix())));
+    for pair in keys.windows(2){
+        let (a, b) = (&pair[0].1, &pair[1].1);
+        if a.rotation.dot(&b.rotation).abs() > 1.0-1e-12{
+            //Without rotation every corner moves in a straight line, so the end boxes cover the motion.
+            continue;
+        }
+        //While rotating, the scaled box stays within a ball around the translation whose radius covers
+        //its farthest corner from the object's origin at the largest scale.
+        let max_scale = [a.scale, b.scale].iter().map(|s| f64::max(s.x.abs(), f64::max(s.y.abs(), s.z.abs()))).fold(0.0, f64::max);
+        let farthest = Vec3::new(
+            f64::max(bbox.x.min.abs(), bbox.x.max.abs()),
+            f64::max(bbox.y.min.abs(), bbox.y.max.abs()),
+            f64::max(bbox.z.min.abs(), bbox.z.max.abs())
+        );
+        let radius = farthest.length()*max_scale;
+        let extent = Vec3::new(radius, radius, radius);
+        for translation in [a.translation, b.translation]{
+            result = Aabb::surrounding(&result, &Aabb::from_points(translation-extent, translation+extent));
+        }
+    }
+    result
+}
+
+///Returns the box enclosing the eight transformed corners of bbox.
+pub(crate) fn transform_box(bbox: &Aabb, transform: &Mat4) -> Aabb{
+    if !bbox.is_bounded(){
+        return *bbox;
+    }
+    let mut result = EMPTY_BOX;
+    for i in 0..8{
+        let corner = Vec3::new(
+            if i & 1 == 0 {bbox.x.min} else {bbox.x.max},
+            if i & 2 == 0 {bbox.y.min} else {bbox.y.max},
+            if i & 4 == 0 {bbox.z.min} else {bbox.z.max}
+        );
+        let p = transform.transform_point(&corner);
+        result = Aabb::surrounding(&result, &Aabb::from_points(p, p));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::utils::PI;
+    use super::*;
+
+    fn keys() -> Keyframes<Vec3>{
+        //Given out of order on purpose.
+        Keyframes::new(vec![
+            (1.0, Vec3::new(2.0, 0.0, 0.0)),
+            (0.0, Vec3::new(0.0, 0.0, 0.0)),
+            (3.0, Vec3::new(2.0, 4.0, 0.0))
+        ]).unwrap()
+    }
+
+    #[test]
+    fn interpolates_between_keys(){
+        let keys = keys();
+        assert_eq!(keys.at(0.5).x, 1.0);
+        assert_eq!(keys.at(1.0).x, 2.0);
+        let p = keys.at(2.0);
+        assert_eq!((p.x, p.y), (2.0, 2.0));
+    }
+
+    #[test]
+    fn holds_still_outside_the_keys(){
+        let keys = keys();
+        assert_eq!(keys.at(-1.0).x, 0.0);
+        assert_eq!(keys.at(10.0).y, 4.0);
+        assert!(!keys.is_constant());
+        assert_eq!(Keyframes::constant(Vec3::new(1.0, 2.0, 3.0)).at(5.0).z, 3.0);
+    }
+
+    #[test]
+    fn needs_a_key(){
+        assert!(Keyframes::<Vec3>::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn slerps_rotations(){
+        let pose = |angle: f64| Pose{
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angle),
+            scale: Vec3::new(1.0, 1.0, 1.0)
+        };
+        let poses = Keyframes::new(vec![(0.0, pose(0.0)), (1.0, pose(PI/2.0))]).unwrap();
+        //Halfway through a quarter turn around y, +x has turned 45 degrees toward -z.
+        let p = poses.at(0.5).matrix().transform_point(&Vec3::new(1.0, 0.0, 0.0));
+        let half = f64::sqrt(0.5);
+        assert!((p.x-half).abs() < 1e-9 && p.y.abs() < 1e-9 && (p.z+half).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inverse_matrix_undoes_matrix(){
+        let pose = Pose{
+            translation: Vec3::new(1.0, -2.0, 3.0),
+            rotation: Quaternion::from_euler(Vec3::new(0.3, -1.1, 2.0)),
+            scale: Vec3::new(2.0, 0.5, 3.0)
+        };
+        let p = Vec3::new(0.7, 1.3, -4.0);
+        let q = pose.inverse_matrix().transform_point(&pose.matrix().transform_point(&p));
+        assert!((p-q).length() < 1e-9);
+    }
+
+    #[test]
+    fn animated_box_covers_every_pose(){
+        let bbox = Aabb::from_points(Vec3::new(-1.0, -0.5, -2.0), Vec3::new(1.0, 0.5, 2.0));
+        let poses = Keyframes::new(vec![
+            (0.0, Pose{translation: Vec3::new(0.0, 0.0, 0.0), rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0), scale: Vec3::new(1.0, 1.0, 1.0)}),
+            (1.0, Pose{
+                translation: Vec3::new(3.0, 1.0, 0.0),
+                rotation: Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 2.0),
+                scale: Vec3::new(2.0, 1.0, 1.0)
+            })
+        ]).unwrap();
+        let animated = animated_box(&bbox, &poses);
+        for step in 0..=20{
+            let moved = transform_box(&bbox, &poses.at(step as f64/20.0).matrix());
+            for axis in 0..3{
+                assert!(animated.axis(axis).min <= moved.axis(axis).min + 1e-9);
+                assert!(animated.axis(axis).max >= moved.axis(axis).max - 1e-9);
+            }
+        }
+    }
+}
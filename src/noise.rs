@@ -0,0 +1,117 @@
+use rand::{Rng, SeedableRng};
+use crate::{sampler::{splitmix64, Sampler}, vec3::{dot, random_unit_vector, Vec3}};
+
+const POINT_COUNT: usize = 256;
+
+///Ken Perlin's gradient noise, using random unit gradients at lattice points.
+pub struct Perlin{
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+impl Perlin{
+    ///Builds the gradient and permutation tables from seed, so the same seed always gives the same noise.
+    pub fn new(seed: u64) -> Perlin{
+        let mut rng = Sampler::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT).map(|_| random_unit_vector(&mut rng)).collect();
+        let perm_x = Perlin::generate_perm(&mut rng);
+        let perm_y = Perlin::generate_perm(&mut rng);
+        let perm_z = Perlin::generate_perm(&mut rng);
+        Perlin{gradients, perm_x, perm_y, perm_z}
+    }
+    fn generate_perm(rng: &mut impl Rng) -> Vec<usize>{
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev(){
+            let target = rng.gen_range(0..=i);
+            perm.swap(i, target);
+        }
+        perm
+    }
+    ///Returns smoothly varying noise in roughly [-1, 1].
+    pub fn noise(&self, p: &Vec3) -> f64{
+        let (fx, fy, fz) = (f64::floor(p.x), f64::floor(p.y), f64::floor(p.z));
+        let (u, v, w) = (p.x-fx, p.y-fy, p.z-fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        //Hermite smoothing of the interpolation weights hides the lattice.
+        let uu = u*u*(3.0-2.0*u);
+        let vv = v*v*(3.0-2.0*v);
+        let ww = w*w*(3.0-2.0*w);
+
+        let mut accumulated = 0.0;
+        for di in 0..2{
+            for dj in 0..2{
+                for dk in 0..2{
+                    let index = self.perm_x[((i+di) & 255) as usize]
+                        ^ self.perm_y[((j+dj) & 255) as usize]
+                        ^ self.perm_z[((k+dk) & 255) as usize];
+                    let weight = Vec3::new(u-di as f64, v-dj as f64, w-dk as f64);
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    accumulated += (a*uu + (1.0-a)*(1.0-uu))
+                        * (b*vv + (1.0-b)*(1.0-vv))
+                        * (c*ww + (1.0-c)*(1.0-ww))
+                        * dot(&self.gradients[index], &weight);
+                }
+            }
+        }
+        accumulated
+    }
+    ///Sums octaves of noise, each at double the frequency and half the amplitude of the last.
+    pub fn fbm(&self, p: &Vec3, octaves: u32) -> f64{
+        let mut accumulated = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves{
+            accumulated += weight*self.noise(&point);
+            weight *= 0.5;
+            point = point*2.0;
+        }
+        accumulated
+    }
+    ///Like fbm, but sums the absolute value of each octave, giving sharp creases.
+    pub fn turbulence(&self, p: &Vec3, octaves: u32) -> f64{
+        let mut accumulated = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves{
+            accumulated += weight*f64::abs(self.noise(&point));
+            weight *= 0.5;
+            point = point*2.0;
+        }
+        accumulated
+    }
+}
+
+///Steven Worley's cellular noise: one random feature point per unit cell.
+pub struct Worley{
+    seed: u64
+}
+impl Worley{
+    pub fn new(seed: u64) -> Worley{
+        Worley{seed}
+    }
+    ///Returns the feature point of a cell, derived by hashing its coordinates.
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Vec3{
+        let mut hash = splitmix64(self.seed);
+        hash = splitmix64(hash ^ i as u64);
+        hash = splitmix64(hash ^ j as u64);
+        hash = splitmix64(hash ^ k as u64);
+        let unit = |bits: u64| (bits & 0x1fffff) as f64/0x200000 as f64;
+        Vec3::new(i as f64 + unit(hash), j as f64 + unit(hash >> 21), k as f64 + unit(hash >> 42))
+    }
+    ///Returns the distance from p to the nearest feature point.
+    pub fn distance(&self, p: &Vec3) -> f64{
+        let (i, j, k) = (f64::floor(p.x) as i64, f64::floor(p.y) as i64, f64::floor(p.z) as i64);
+        let mut nearest = f64::MAX;
+        for di in -1..=1{
+            for dj in -1..=1{
+                for dk in -1..=1{
+                    let d = (self.feature_point(i+di, j+dj, k+dk) - *p).length_squared();
+                    nearest = f64::min(nearest, d);
+                }
+            }
+        }
+        f64::sqrt(nearest)
+    }
+}
@@ -0,0 +1,369 @@
+//!Wavefront OBJ and MTL import.
+//!
+//!Faces are fan-triangulated and split into one triangle mesh per group and material.
+//!MTL materials are mapped onto the existing materials: emissive (`Ke`) to DiffuseLight,
+//!transparent (`d` < 1 or `Tr` > 0) to Dielectric with index `Ni`, specular (`Ks` brighter than `Kd`)
+//!to Metal with a fuzz derived from `Ns`, and everything else to Lambertian with albedo `Kd`,
+//!or textured with the image in `map_Kd` when there is one.
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use crate::{
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    input,
+    scene_file::SceneError,
+    texture::ImageTexture,
+    triangle::{MeshData, TriangleMesh},
+    vec3::Vec3
+};
+
+///Material parameters read from an MTL file.
+struct MtlMaterial{
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    ns: f64,
+    ni: f64,
+    dissolve: f64,
+    map_kd: Option<String>
+}
+impl Default for MtlMaterial{
+    fn default() -> MtlMaterial{
+        MtlMaterial{
+            kd: Vec3::new(0.8, 0.8, 0.8),
+            ks: Vec3::new(0.0, 0.0, 0.0),
+            ke: Vec3::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            dissolve: 1.0,
+            map_kd: None
+        }
+    }
+}
+impl MtlMaterial{
+    ///Builds the material, loading any diffuse texture map relative to directory.
+    fn build(&self, directory: &Path) -> Result<Arc<dyn Material>, SceneError>{
+        let max = |v: Vec3| f64::max(v.x, f64::max(v.y, v.z));
+        if max(self.ke) > 0.0{
+            Ok(Arc::new(DiffuseLight::new(self.ke)))
+        }else if self.dissolve < 1.0{
+            Ok(Arc::new(Dielectric::new(self.ni)))
+        }else if max(self.ks) > max(self.kd) && self.map_kd.is_none(){
+            //Maps the Phong exponent to a fuzz radius: high exponents give sharp reflections.
+            let fuzz = f64::min(f64::sqrt(2.0/(self.ns+2.0)), 1.0);
+            Ok(Arc::new(Metal::new(self.ks, fuzz)))
+        }else if let Some(map) = &self.map_kd{
+            let image = input::load(&directory.join(map)).map_err(|e| SceneError{line: None, message: e.to_string()})?;
+            Ok(Arc::new(Lambertian::from_texture(Arc::new(ImageTexture::new(image)))))
+        }else{
+            Ok(Arc::new(Lambertian::new(self.kd)))
+        }
+    }
+}
+
+///Triangles collected for one group and material, with vertices keyed by their
+///(position, uv, normal) indices in the OBJ file.
+#[derive(Default)]
+struct MeshBuilder{
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    keys: Vec<(usize, Option<usize>, Option<usize>)>,
+    indices: Vec<[u32; 3]>
+}
+impl MeshBuilder{
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>)) -> u32{
+        let next = self.keys.len() as u32;
+        *self.vertices.entry(key).or_insert_with(|| {
+            self.keys.push(key);
+            next
+        })
+    }
+    ///Resolves the vertex keys against the OBJ buffers. Normals and uvs are only kept
+    ///when every vertex of the mesh has one.
+    fn build(self, positions: &[Vec3], uvs: &[[f64; 2]], normals: &[Vec3], material: Arc<dyn Material>) -> MeshData{
+        let mesh_normals = if self.keys.iter().all(|k| k.2.is_some()){
+            self.keys.iter().map(|k| normals[k.2.unwrap()]).collect()
+        }else{
+            Vec::new()
+        };
+        let mesh_uvs = if self.keys.iter().all(|k| k.1.is_some()){
+            self.keys.iter().map(|k| uvs[k.1.unwrap()]).collect()
+        }else{
+            Vec::new()
+        };
+        MeshData{
+            positions: self.keys.iter().map(|k| positions[k.0]).collect(),
+            normals: mesh_normals,
+            uvs: mesh_uvs,
+            indices: self.indices,
+            material
+        }
+    }
+}
+
+fn parse_floats<const N: usize>(args: &[&str], line: usize) -> Result<[f64; N], SceneError>{
+    if args.len() < N{
+        return Err(SceneError::on_line(line, format!("expected {} numbers", N)));
+    }
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args){
+        *value = arg.parse().map_err(|_| SceneError::on_line(line, format!("invalid number `{}`", arg)))?;
+    }
+    Ok(values)
+}
+
+///Resolves a 1-based (or negative, relative to the end) OBJ index into a buffer of length len.
+fn resolve_index(arg: &str, len: usize, line: usize) -> Result<usize, SceneError>{
+    let index: i64 = arg.parse().map_err(|_| SceneError::on_line(line, format!("invalid index `{}`", arg)))?;
+    let resolved = if index < 0 {len as i64 + index} else {index - 1};
+    if resolved < 0 || resolved >= len as i64{
+        return Err(SceneError::on_line(line, format!("index {} out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+fn load_mtl(path: &Path, materials: &mut HashMap<String, Arc<dyn Material>>) -> Result<(), SceneError>{
+    let source = fs::read_to_string(path).map_err(|e| SceneError{
+        line: None,
+        message: format!("could not read {}: {}", path.display(), e)
+    })?;
+    let in_file = |e: SceneError| SceneError{line: None, message: format!("{}: {}", path.display(), e)};
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (number, text) in source.lines().enumerate(){
+        let line = number+1;
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let Some((keyword, args)) = tokens.split_first() else {continue};
+        if *keyword == "newmtl"{
+            if let Some((name, material)) = current.take(){
+                materials.insert(name, material.build(directory).map_err(in_file)?);
+            }
+            current = Some((args.join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let Some((_, material)) = current.as_mut() else {continue};
+        match *keyword{
+            "Kd" | "Ks" | "Ke" => {
+                let [r, g, b] = parse_floats::<3>(args, line).map_err(in_file)?;
+                let color = Vec3::new(r, g, b);
+                match *keyword{
+                    "Kd" => material.kd = color,
+                    "Ks" => material.ks = color,
+                    _ => material.ke = color
+                }
+            }
+            "Ns" => material.ns = parse_floats::<1>(args, line).map_err(in_file)?[0],
+            "Ni" => material.ni = parse_floats::<1>(args, line).map_err(in_file)?[0],
+            "d" => material.dissolve = parse_floats::<1>(args, line).map_err(in_file)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(args, line).map_err(in_file)?[0],
+            //Options such as -s or -o are not supported; the file name is the last argument.
+            "map_Kd" => material.map_kd = args.last().map(|s| s.to_string()),
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current{
+        materials.insert(name, material.build(directory).map_err(in_file)?);
+    }
+    Ok(())
+}
+
+///Loads an OBJ file, and the MTL libraries it references, as triangle meshes.
+///Faces without a material, or whose material no library defines, use default_material.
+///A library that cannot be found is an error, reported on the `mtllib` line.
+pub fn load_obj(path: &Path, default_material: Arc<dyn Material>) -> Result<Vec<TriangleMesh>, SceneError>{
+    let source = fs::read_to_string(path).map_err(|e| SceneError{
+        line: None,
+        message: format!("could not read {}: {}", path.display(), e)
+    })?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let in_file = |e: SceneError| SceneError{line: None, message: format!("{}: {}", path.display(), e)};
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<[f64; 2]> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    //Meshes are keyed by group and material name, in the order they first appear.
+    let mut meshes: Vec<((String, String), MeshBuilder)> = Vec::new();
+    let mut group = String::new();
+    let mut material = String::new();
+
+    for (number, text) in source.lines().enumerate(){
+        let line = number+1;
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let Some((keyword, args)) = tokens.split_first() else {continue};
+        match *keyword{
+            "v" => {
+                let [x, y, z] = parse_floats::<3>(args, line).map_err(in_file)?;
+                positions.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                //The v coordinate is optional and defaults to 0.
+                let [u] = parse_floats::<1>(args, line).map_err(in_file)?;
+                let v = if args.len() > 1 {parse_floats::<2>(args, line).map_err(in_file)?[1]} else {0.0};
+                uvs.push([u, v]);
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats::<3>(args, line).map_err(in_file)?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            "g" | "o" => group = args.join(" "),
+            "usemtl" => material = args.join(" "),
+            "mtllib" => {
+                for library in args{
+                    let library = directory.join(library);
+                    if !library.is_file(){
+                        let message = format!("material library {} not found", library.display());
+                        return Err(in_file(SceneError::on_line(line, message)));
+                    }
+                    load_mtl(&library, &mut materials)?;
+                }
+            }
+            "f" => {
+                if args.len() < 3{
+                    return Err(in_file(SceneError::on_line(line, "face needs at least three vertices".to_string())));
+                }
+                let key = (group.clone(), material.clone());
+                let builder = match meshes.iter().position(|(k, _)| *k == key){
+                    Some(index) => &mut meshes[index].1,
+                    None => {
+                        meshes.push((key, MeshBuilder::default()));
+                        &mut meshes.last_mut().unwrap().1
+                    }
+                };
+
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in args{
+                    let mut parts = arg.split('/');
+                    let p = resolve_index(parts.next().unwrap_or(""), positions.len(), line).map_err(in_file)?;
+                    let t = match parts.next(){
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, uvs.len(), line).map_err(in_file)?),
+                        _ => None
+                    };
+                    let n = match parts.next(){
+                        Some(n) if !n.is_empty() => Some(resolve_index(n, normals.len(), line).map_err(in_file)?),
+                        _ => None
+                    };
+                    corners.push(builder.vertex((p, t, n)));
+                }
+                for k in 1..corners.len()-1{
+                    builder.indices.push([corners[0], corners[k], corners[k+1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(meshes.into_iter().map(|((_, material), builder)| {
+        let material = materials.get(&material).cloned().unwrap_or_else(|| default_material.clone());
+        TriangleMesh::new(builder.build(&positions, &uvs, &normals, material))
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests{
+    use std::path::PathBuf;
+    use crate::{hittable::{HitRecord, Hittable}, utils::Interval, Ray};
+    use super::*;
+
+    ///Writes files into a fresh directory for one test and returns the path of the first.
+    fn write(test: &str, files: &[(&str, &str)]) -> PathBuf{
+        let directory = std::env::temp_dir().join(format!("obj-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files{
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        directory.join(files[0].0)
+    }
+    fn default_material() -> Arc<dyn Material>{
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+    ///Shoots a ray straight down the z axis at (x, y).
+    fn hit(meshes: &[TriangleMesh], x: f64, y: f64) -> Option<HitRecord>{
+        let r = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        meshes.iter().filter_map(|mesh| mesh.intersect(&r, &Interval::new(0.001, f64::INFINITY))).next()
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n";
+
+    #[test]
+    fn triangulates_polygons_with_uvs(){
+        let path = write("uvs", &[("square.obj", &format!("{}f 1/1 2/2 3/3 4/4\n", SQUARE))]);
+        let meshes = load_obj(&path, default_material()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        for (x, y) in [(0.25, 0.75), (0.75, 0.25)]{
+            let record = hit(&meshes, x, y).unwrap();
+            assert!((record.u-x).abs() < 1e-9 && (record.v-y).abs() < 1e-9);
+            assert!((record.normal.z-1.0).abs() < 1e-9);
+        }
+        assert!(hit(&meshes, 1.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn resolves_negative_indices(){
+        let path = write("negative", &[("square.obj", &format!("{}f -4 -3 -2 -1\n", SQUARE))]);
+        let meshes = load_obj(&path, default_material()).unwrap();
+        assert!(hit(&meshes, 0.25, 0.75).is_some());
+    }
+
+    #[test]
+    fn single_coordinate_uvs_default_v_to_zero(){
+        let path = write("vt", &[("tri.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5\nf 1/1 2/1 3/1\n")]);
+        let record = hit(&load_obj(&path, default_material()).unwrap(), 0.2, 0.2).unwrap();
+        assert!((record.u-0.5).abs() < 1e-9 && record.v == 0.0);
+    }
+
+    #[test]
+    fn splits_meshes_by_material(){
+        let obj = "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 3 0 0\nv 4 0 0\nv 3 1 0\nusemtl glow\nf 1 2 3\nusemtl matte\nf 4 5 6\n";
+        let mtl = "newmtl glow\nKe 4 4 4\nnewmtl matte\nKd 0.8 0.1 0.1\n";
+        let path = write("mtl", &[("lamp.obj", obj), ("lamp.mtl", mtl)]);
+        let meshes = load_obj(&path, default_material()).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert!(hit(&meshes, 0.2, 0.2).unwrap().material.is_emissive());
+        assert!(!hit(&meshes, 3.2, 0.2).unwrap().material.is_emissive());
+        assert_eq!(meshes.iter().filter(|mesh| mesh.is_emitter()).count(), 1);
+    }
+
+    #[test]
+    fn undefined_materials_fall_back_to_the_default_material(){
+        let path = write("undefined", &[("tri.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glow\nf 1 2 3\n")]);
+        let meshes = load_obj(&path, Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0)))).unwrap();
+        assert!(hit(&meshes, 0.2, 0.2).unwrap().material.is_emissive());
+    }
+
+    #[test]
+    fn reports_missing_libraries(){
+        let path = write("missing", &[("tri.obj", "v 0 0 0\nmtllib nowhere.mtl\n")]);
+        match load_obj(&path, default_material()){
+            Ok(_) => panic!("the library should not be found"),
+            Err(e) => assert!(e.to_string().contains("line 2: material library") && e.to_string().ends_with("nowhere.mtl not found"), "{}", e)
+        }
+    }
+
+    #[test]
+    fn reports_malformed_lines(){
+        let cases = [
+            ("range", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", "line 4: index 4 out of range"),
+            ("zero", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n", "line 4: index 0 out of range"),
+            ("number", "v 0 0 0\nv 1 zero 0\n", "line 2: invalid number `zero`"),
+            ("short", "v 0 0\n", "line 1: expected 3 numbers"),
+            ("face", "v 0 0 0\nv 1 0 0\nf 1 2\n", "line 3: face needs at least three vertices")
+        ];
+        for (test, obj, message) in cases{
+            let path = write(test, &[("bad.obj", obj)]);
+            match load_obj(&path, default_material()){
+                Ok(_) => panic!("{} should not load", test),
+                Err(e) => assert!(e.to_string().ends_with(message), "{}", e)
+            }
+        }
+        assert!(load_obj(Path::new("no/such/file.obj"), default_material()).is_err());
+    }
+
+    #[test]
+    fn reports_malformed_libraries(){
+        let path = write("badmtl", &[("tri.obj", "mtllib bad.mtl\n"), ("bad.mtl", "newmtl red\nKd 1 0\n")]);
+        match load_obj(&path, default_material()){
+            Ok(_) => panic!("the library should not load"),
+            Err(e) => assert!(e.to_string().contains("bad.mtl: line 2: expected 3 numbers"), "{}", e)
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+///An orthonormal basis whose w axis points along a given direction.
+#[derive(Copy, Clone)]
+pub struct Onb{
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+impl Onb{
+    pub fn new(n: &Vec3) -> Onb{
+        let w = unit_vector(n);
+        //Any helper vector works as long as it is not parallel to w.
+        let a = if f64::abs(w.x) > 0.9 {Vec3::new(0.0, 1.0, 0.0)} else {Vec3::new(1.0, 0.0, 0.0)};
+        let v = unit_vector(&cross(&w, &a));
+        let u = cross(&w, &v);
+        Onb{u, v, w}
+    }
+    ///Returns a basis whose w axis points along n and whose u axis is as close as possible to tangent,
+    ///or any basis around n if tangent is parallel to it.
+    pub fn aligned(n: &Vec3, tangent: &Vec3) -> Onb{
+        let w = unit_vector(n);
+        let along = *tangent - w*dot(tangent, &w);
+        if along.near_zero(){
+            return Onb::new(n);
+        }
+        let u = unit_vector(&along);
+        let v = cross(&w, &u);
+        Onb{u, v, w}
+    }
+    ///Converts a vector given in world coordinates to this basis' coordinates.
+    pub fn local(&self, v: &Vec3) -> Vec3{
+        Vec3::new(dot(v, &self.u), dot(v, &self.v), dot(v, &self.w))
+    }
+    ///Converts a vector given in this basis' coordinates to world coordinates.
+    pub fn transform(&self, v: &Vec3) -> Vec3{
+        self.u*v.x + self.v*v.y + self.w*v.z
+    }
+}
@@ -0,0 +1,264 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::{image::Image, utils::Interval, vec3::Vec3};
+
+///Bits per channel used when encoding PNG files.
+#[derive(Copy, Clone)]
+pub enum PngDepth{
+    Eight,
+    Sixteen
+}
+
+pub fn linear_to_gamma(x: f64) -> f64{
+    if x > 0.0 {f64::sqrt(x)} else {0.0}
+}
+///Gamma-encodes a linear color and clamps each channel to [0, 1).
+pub fn encode_color(v: Vec3) -> [f64; 3]{
+    let intensity: Interval = Interval { min: 0.0, max: 0.999};
+
+    let r = linear_to_gamma(v.x);
+    let g = linear_to_gamma(v.y);
+    let b = linear_to_gamma(v.z);
+
+    [intensity.clamp(r), intensity.clamp(g), intensity.clamp(b)]
+}
+///Gamma-encodes and clamps a linear color to 8 bits per channel.
+pub fn to_bytes(v: Vec3) -> [u8; 3]{
+    encode_color(v).map(|c| (c * 256.0) as u8)
+}
+///Gamma-encodes and clamps a linear color to 16 bits per channel, so that white is 65535.
+pub fn to_words(v: Vec3) -> [u16; 3]{
+    let intensity = Interval::new(0.0, 1.0);
+    [v.x, v.y, v.z].map(|c| f64::round(intensity.clamp(linear_to_gamma(c)) * 65535.0) as u16)
+}
+pub fn write_color(out: &mut impl Write, v: Vec3) -> io::Result<()>{
+    let [r, g, b] = to_bytes(v);
+    writeln!(out, "{} {} {}", r, g, b)
+}
+///Writes the image as an ASCII (P3) PPM file.
+pub fn write_ppm(image: &Image, out: &mut impl Write) -> io::Result<()>{
+    write!(out, "P3\n{} {}\n255\n", image.width(), image.height())?;
+    for color in image.pixels(){
+        write_color(out, *color)?;
+    }
+    Ok(())
+}
+///Writes the image as a PNG file with 8 or 16 bits per channel.
+pub fn write_png(image: &Image, out: impl Write, depth: PngDepth) -> io::Result<()>{
+    let mut encoder = png::Encoder::new(out, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    //Samples are encoded with linear_to_gamma, a power of 1/2, rather than the sRGB curve.
+    encoder.set_source_gamma(png::ScaledFloat::new(0.5));
+
+    let data: Vec<u8> = match depth{
+        PngDepth::Eight => {
+            encoder.set_depth(png::BitDepth::Eight);
+            image.pixels().iter().flat_map(|v| to_bytes(*v)).collect()
+        }
+        PngDepth::Sixteen => {
+            //PNG stores 16-bit samples in big-endian order.
+            encoder.set_depth(png::BitDepth::Sixteen);
+            image.pixels().iter().flat_map(|v| to_words(*v)).flat_map(u16::to_be_bytes).collect()
+        }
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+///Writes the image as a little-endian Portable Float Map, storing rows from bottom to top.
+pub fn write_pfm(image: &Image, out: &mut impl Write) -> io::Result<()>{
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for j in (0..image.height()).rev(){
+        for i in 0..image.width(){
+            let v = image.get(i, j);
+            for c in [v.x, v.y, v.z]{
+                out.write_all(&(c as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+///Packs a linear color into Radiance's shared-exponent RGBE encoding.
+pub fn to_rgbe(v: Vec3) -> [u8; 4]{
+    let max = f64::max(v.x, f64::max(v.y, v.z));
+    if max < 1e-32{
+        return [0, 0, 0, 0];
+    }
+    //Equivalent to frexp: max = mantissa * 2^exponent with mantissa in [0.5, 1).
+    let exponent = f64::floor(f64::log2(max)) as i32 + 1;
+    let scale = 256.0/f64::powi(2.0, exponent);
+    [
+        (f64::max(v.x, 0.0)*scale) as u8,
+        (f64::max(v.y, 0.0)*scale) as u8,
+        (f64::max(v.z, 0.0)*scale) as u8,
+        (exponent+128) as u8
+    ]
+}
+///Writes the image as an uncompressed Radiance RGBE (.hdr) file.
+pub fn write_hdr(image: &Image, out: &mut impl Write) -> io::Result<()>{
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height(), image.width())?;
+    for color in image.pixels(){
+        out.write_all(&to_rgbe(*color))?;
+    }
+    Ok(())
+}
+///Writes the image as a single-part, uncompressed scanline OpenEXR file with 32-bit float RGB channels.
+pub fn write_exr(image: &Image, out: &mut impl Write) -> io::Result<()>{
+    let (width, height) = (image.width(), image.height());
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    //Channels must be listed in alphabetical order; type 2 is FLOAT.
+    let mut channels: Vec<u8> = Vec::new();
+    for name in ["B", "G", "R"]{
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let window: Vec<u8> = [0, 0, width-1, height-1].iter().flat_map(|x: &i32| x.to_le_bytes()).collect();
+
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    out.write_all(&header)?;
+
+    //Each scanline is its own chunk: its y coordinate, its size, then every channel's row in turn.
+    let line_size = width as usize*3*4;
+    let chunk_size = (4+4+line_size) as u64;
+    let first_chunk = (header.len() + height as usize*8) as u64;
+    for j in 0..height as u64{
+        out.write_all(&(first_chunk + j*chunk_size).to_le_bytes())?;
+    }
+    for j in 0..height{
+        out.write_all(&j.to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        for channel in [|v: Vec3| v.z, |v: Vec3| v.y, |v: Vec3| v.x]{
+            for i in 0..width{
+                out.write_all(&(channel(image.get(i, j)) as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]){
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+///Saves the image to path, choosing the format from the file extension.
+///PFM, HDR and EXR files keep the linear, unclamped colors; PPM and PNG are gamma-encoded and clamped.
+pub fn save(image: &Image, path: &Path, png_depth: PngDepth) -> io::Result<()>{
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    let write: fn(&Image, &mut BufWriter<File>, PngDepth) -> io::Result<()> = match extension.as_deref(){
+        Some("ppm") => |image, out, _| write_ppm(image, out),
+        Some("png") => |image, out, depth| write_png(image, out, depth),
+        Some("pfm") => |image, out, _| write_pfm(image, out),
+        Some("hdr") => |image, out, _| write_hdr(image, out),
+        Some("exr") => |image, out, _| write_exr(image, out),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported output format: {}", path.display())
+            ));
+        }
+    };
+
+    let mut out = BufWriter::new(File::create(path)?);
+    write(image, &mut out, png_depth)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32{
+        u32::from_le_bytes(bytes[at..at+4].try_into().unwrap())
+    }
+    fn f32_at(bytes: &[u8], at: usize) -> f32{
+        f32::from_le_bytes(bytes[at..at+4].try_into().unwrap())
+    }
+    ///Reads a null-terminated string at position, moving position past the terminator.
+    fn name(bytes: &[u8], position: &mut usize) -> String{
+        let end = *position + bytes[*position..].iter().position(|b| *b == 0).unwrap();
+        let name = String::from_utf8(bytes[*position..end].to_vec()).unwrap();
+        *position = end+1;
+        name
+    }
+
+    #[test]
+    fn writes_exr(){
+        let mut image = Image::new(3, 2);
+        for j in 0..2{
+            for i in 0..3{
+                image.set(i, j, Vec3::new(i as f64, j as f64, 0.5 + (i+3*j) as f64));
+            }
+        }
+        let mut bytes = Vec::new();
+        write_exr(&image, &mut bytes).unwrap();
+
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(u32_at(&bytes, 4), 2);
+
+        //Attributes are a name, a type name, a size and a value, until an empty name ends the header.
+        let mut attributes = Vec::new();
+        let mut position = 8;
+        loop{
+            let attribute = name(&bytes, &mut position);
+            if attribute.is_empty(){
+                break;
+            }
+            let kind = name(&bytes, &mut position);
+            let size = u32_at(&bytes, position) as usize;
+            attributes.push((attribute, kind, bytes[position+4..position+4+size].to_vec()));
+            position += 4+size;
+        }
+        let header_size = position;
+        let names: Vec<&str> = attributes.iter().map(|(attribute, _, _)| attribute.as_str()).collect();
+        assert_eq!(names, ["channels", "compression", "dataWindow", "displayWindow", "lineOrder",
+            "pixelAspectRatio", "screenWindowCenter", "screenWindowWidth"]);
+
+        let (_, kind, channels) = &attributes[0];
+        assert_eq!(kind, "chlist");
+        let mut expected = Vec::new();
+        for channel in [b'B', b'G', b'R']{
+            //The name, FLOAT pixels, linear and reserved bytes, then x and y sampling.
+            expected.extend_from_slice(&[channel, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        expected.push(0);
+        assert_eq!(*channels, expected);
+        assert_eq!(attributes[1].2, [0]);
+        let window: Vec<u8> = [0u32, 0, 2, 1].iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!((attributes[2].1.as_str(), &attributes[2].2), ("box2i", &window));
+
+        //One offset per scanline, each pointing at a chunk of its y, its size, then the B, G and R rows.
+        let line_size = 3*3*4;
+        let offset_at = |at: usize| u64::from_le_bytes(bytes[at..at+8].try_into().unwrap()) as usize;
+        let offsets: Vec<usize> = (0..2).map(|j| offset_at(header_size + 8*j)).collect();
+        assert_eq!(offsets, [header_size+16, header_size+16+8+line_size]);
+        assert_eq!(bytes.len(), offsets[1]+8+line_size);
+        for (j, offset) in offsets.into_iter().enumerate(){
+            assert_eq!((u32_at(&bytes, offset), u32_at(&bytes, offset+4)), (j as u32, line_size as u32));
+            for i in 0..3{
+                let sample = |channel: usize| f32_at(&bytes, offset + 8 + channel*12 + i*4);
+                let expected = image.get(i as i32, j as i32);
+                assert_eq!((sample(2), sample(1), sample(0)), (expected.x as f32, expected.y as f32, expected.z as f32));
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{
+    hittable::Hittable,
+    microfacet::Ggx,
+    onb::Onb,
+    sampler::Sampler,
+    utils::PI,
+    vec3::{dot, random_cosine_direction, random_unit_vector, reflect, unit_vector, Vec3}
+};
+use rand::Rng;
+
+///A probability density over directions, which can be sampled and evaluated.
+pub trait Pdf{
+    ///Returns the density over solid angle of generating direction.
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self, rng: &mut Sampler) -> Vec3;
+}
+
+///Directions spread evenly over the whole sphere.
+pub struct SpherePdf;
+impl Pdf for SpherePdf{
+    fn value(&self, _direction: &Vec3) -> f64{
+        1.0/(4.0*PI)
+    }
+    fn generate(&self, rng: &mut Sampler) -> Vec3{
+        random_unit_vector(rng)
+    }
+}
+
+///Directions on the hemisphere around a normal, weighted by the cosine to it.
+pub struct CosinePdf{
+    basis: Onb
+}
+impl CosinePdf{
+    pub fn new(normal: &Vec3) -> CosinePdf{
+        CosinePdf{basis: Onb::new(normal)}
+    }
+}
+impl Pdf for CosinePdf{
+    fn value(&self, direction: &Vec3) -> f64{
+        let cosine = dot(&unit_vector(direction), &self.basis.w);
+        f64::max(cosine/PI, 0.0)
+    }
+    fn generate(&self, rng: &mut Sampler) -> Vec3{
+        self.basis.transform(&random_cosine_direction(rng))
+    }
+}
+
+///Directions scattered by the Henyey-Greenstein phase function from light traveling along incoming.
+///Positive g favors forward scattering, negative g back scattering, and zero scatters evenly.
+pub struct HenyeyGreensteinPdf{
+    basis: Onb,
+    g: f64
+}
+impl HenyeyGreensteinPdf{
+    pub fn new(incoming: &Vec3, g: f64) -> HenyeyGreensteinPdf{
+        HenyeyGreensteinPdf{basis: Onb::new(incoming), g}
+    }
+    ///Returns the phase function for an angle with the given cosine between the incoming and outgoing directions.
+    pub fn phase(cos_theta: f64, g: f64) -> f64{
+        let denominator = 1.0 + g*g - 2.0*g*cos_theta;
+        (1.0-g*g)/(4.0*PI*denominator*f64::sqrt(denominator))
+    }
+}
+impl Pdf for HenyeyGreensteinPdf{
+    fn value(&self, direction: &Vec3) -> f64{
+        HenyeyGreensteinPdf::phase(dot(&unit_vector(direction), &self.basis.w), self.g)
+    }
+    fn generate(&self, rng: &mut Sampler) -> Vec3{
+        let g = self.g;
+        let xi = rng.gen::<f64>();
+        //Inverts the cumulative distribution of the phase function over cos(theta).
+        let cos_theta = if f64::abs(g) < 1e-3{
+            1.0 - 2.0*xi
+        }else{
+            let s = (1.0-g*g)/(1.0-g+2.0*g*xi);
+            (1.0 + g*g - s*s)/(2.0*g)
+        };
+        let cos_theta = f64::clamp(cos_theta, -1.0, 1.0);
+        let sin_theta = f64::sqrt(1.0-cos_theta*cos_theta);
+        let phi = 2.0*PI*rng.gen::<f64>();
+        self.basis.transform(&Vec3::new(f64::cos(phi)*sin_theta, f64::sin(phi)*sin_theta, cos_theta))
+    }
+}
+
+///Directions reflected off GGX microfacets, whose normals are drawn in proportion to their area visible
+///from the outgoing direction.
+pub struct GgxPdf{
+    basis: Onb,
+    ///The unit direction toward the viewer, in the basis' coordinates.
+    outgoing: Vec3,
+    distribution: Ggx
+}
+impl GgxPdf{
+    ///Makes the density for a surface whose normal is the basis' w axis, seen from the unit direction outgoing.
+    pub fn new(basis: Onb, outgoing: &Vec3, distribution: Ggx) -> GgxPdf{
+        GgxPdf{basis, outgoing: basis.local(outgoing), distribution}
+    }
+}
+impl Pdf for GgxPdf{
+    fn value(&self, direction: &Vec3) -> f64{
+        self.distribution.reflection_pdf(&self.outgoing, &self.basis.local(&unit_vector(direction)))
+    }
+    fn generate(&self, rng: &mut Sampler) -> Vec3{
+        let h = self.distribution.sample_visible_normal(&self.outgoing, rng);
+        self.basis.transform(&reflect(&-self.outgoing, &h))
+    }
+}
+
+///Directions from origin toward points on an object, using its pdf_value and random methods.
+pub struct HittablePdf<'a>{
+    object: &'a dyn Hittable,
+    origin: Vec3
+}
+impl<'a> HittablePdf<'a>{
+    pub fn new(object: &'a dyn Hittable, origin: Vec3) -> HittablePdf<'a>{
+        HittablePdf{object, origin}
+    }
+}
+impl Pdf for HittablePdf<'_>{
+    fn value(&self, direction: &Vec3) -> f64{
+        self.object.pdf_value(&self.origin, direction)
+    }
+    fn generate(&self, rng: &mut Sampler) -> Vec3{
+        self.object.random(&self.origin, rng)
+    }
+}
+
+///Checks shared with the tests of other directional densities, such as the environment map's.
+#[cfg(test)]
+pub(crate) mod tests{
+    use std::sync::Arc;
+    use rand::SeedableRng;
+    use crate::{material::Lambertian, quad::Quad};
+    use super::*;
+
+    const COS_BINS: usize = 16;
+    const PHI_BINS: usize = 32;
+    ///Midpoint rule steps per bin along each axis.
+    const STEPS: usize = 16;
+    const SAMPLES: usize = 200_000;
+
+    fn direction(cos_theta: f64, phi: f64) -> Vec3{
+        let sin_theta = f64::sqrt(f64::max(1.0-cos_theta*cos_theta, 0.0));
+        Vec3::new(sin_theta*f64::cos(phi), sin_theta*f64::sin(phi), cos_theta)
+    }
+    fn bin(direction: &Vec3) -> usize{
+        let d = unit_vector(direction);
+        let i = (((d.z+1.0)/2.0*COS_BINS as f64) as usize).min(COS_BINS-1);
+        let j = (((f64::atan2(d.y, d.x)+PI)/(2.0*PI)*PHI_BINS as f64) as usize).min(PHI_BINS-1);
+        i*PHI_BINS + j
+    }
+    ///Returns the probability of each bin over cos(theta) and phi, integrating value by the midpoint rule.
+    ///Solid angle is d(cos theta) d(phi), so every step covers the same amount of it.
+    fn bin_probabilities(value: impl Fn(&Vec3) -> f64) -> Vec<f64>{
+        let (cos_step, phi_step) = (2.0/(COS_BINS*STEPS) as f64, 2.0*PI/(PHI_BINS*STEPS) as f64);
+        let mut probabilities = vec![0.0; COS_BINS*PHI_BINS];
+        for a in 0..COS_BINS*STEPS{
+            for b in 0..PHI_BINS*STEPS{
+                let d = direction(-1.0 + (a as f64+0.5)*cos_step, -PI + (b as f64+0.5)*phi_step);
+                probabilities[(a/STEPS)*PHI_BINS + b/STEPS] += value(&d)*cos_step*phi_step;
+            }
+        }
+        probabilities
+    }
+    ///Checks that directions drawn by generate fall in each bin as often as value says, and returns the total
+    ///probability value gives to the sphere. That is 1 unless generate sometimes makes directions whose value
+    ///is zero, such as reflections below a surface, in which case it must be the fraction of other directions.
+    pub(crate) fn check_sampling(value: impl Fn(&Vec3) -> f64, mut generate: impl FnMut(&mut Sampler) -> Vec3) -> f64{
+        let probabilities = bin_probabilities(&value);
+        let mut frequencies = vec![0.0; COS_BINS*PHI_BINS];
+        let mut rng = Sampler::seed_from_u64(7);
+        let mut kept = 0;
+        for _ in 0..SAMPLES{
+            let d = generate(&mut rng);
+            if value(&d) > 0.0{
+                frequencies[bin(&d)] += 1.0/SAMPLES as f64;
+                kept += 1;
+            }
+        }
+        let kept = kept as f64/SAMPLES as f64;
+        for (n, (p, f)) in probabilities.iter().zip(frequencies.iter()).enumerate(){
+            let sigma = f64::sqrt(p*(1.0-p)/SAMPLES as f64);
+            assert!((p-f).abs() < 5.0*sigma + 1e-3, "bin {}: expected {} but sampled {}", n, p, f);
+        }
+        let total: f64 = probabilities.iter().sum();
+        assert!((total-kept).abs() < 5.0*f64::sqrt(kept*(1.0-kept)/SAMPLES as f64) + 1e-2, "{} of samples kept, but {} expected", kept, total);
+        total
+    }
+    fn check(pdf: &dyn Pdf) -> f64{
+        check_sampling(|d| pdf.value(d), |rng| pdf.generate(rng))
+    }
+
+    #[test]
+    fn sphere_pdf(){
+        assert!((check(&SpherePdf)-1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cosine_pdf(){
+        assert!((check(&CosinePdf::new(&Vec3::new(0.0, 0.0, 1.0)))-1.0).abs() < 1e-3);
+        assert!((check(&CosinePdf::new(&Vec3::new(1.0, -2.0, 0.5)))-1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn hittable_pdf(){
+        let quad = Quad::new(
+            Vec3::new(-1.0, -1.0, 2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 3.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+        );
+        let pdf = HittablePdf::new(&quad, Vec3::new(0.0, 0.0, 0.0));
+        //The edges of the quad cut through bins, which the midpoint rule only approximates.
+        assert!((check(&pdf)-1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn henyey_greenstein_pdf(){
+        for g in [0.0, 0.5, -0.3, 0.0005]{
+            let pdf = HenyeyGreensteinPdf::new(&Vec3::new(0.2, 1.0, -0.4), g);
+            assert!((check(&pdf)-1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn ggx_pdf(){
+        let basis = Onb::aligned(&Vec3::new(0.0, 0.0, 1.0), &Vec3::new(1.0, 0.0, 0.0));
+        for (alpha_x, alpha_y, outgoing) in [
+            (0.3, 0.3, Vec3::new(0.0, 0.0, 1.0)),
+            (0.5, 0.5, Vec3::new(0.6, 0.0, 0.8)),
+            (0.2, 0.6, Vec3::new(-0.3, 0.5, 0.4)),
+            (0.8, 0.8, Vec3::new(0.95, 0.0, 0.1))
+        ]{
+            let pdf = GgxPdf::new(basis, &unit_vector(&outgoing), Ggx::new(alpha_x, alpha_y));
+            let total = check(&pdf);
+            //Reflections off visible microfacets only leave below the surface at grazing angles.
+            assert!(total <= 1.0+1e-2 && total > 0.8, "{}", total);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::{
+    aabb::Aabb,
+    hittable::HitRecord,
+    material::Material,
+    onb::Onb,
+    sampler::Sampler,
+    utils::{Interval, NEAR_NON_NEG, PI, UNIVERSE},
+    vec3::{cross, dot, random_in_unit_disk, unit_vector},
+    Hittable, Ray, Vec3
+};
+
+///Intersects a ray with the plane through point with the given unit normal, returning t.
+fn intersect_plane(r: &Ray, ray_t: &Interval, point: &Vec3, normal: &Vec3) -> Option<f64>{
+    let denominator = dot(normal, &r.direction);
+    if f64::abs(denominator) < 1e-8{
+        return None;
+    }
+    let t = dot(normal, &(*point-r.origin))/denominator;
+    if !ray_t.surrounds(t){
+        return None;
+    }
+    Some(t)
+}
+
+///Converts the density 1/area of picking a point uniformly on a flat surface into a density over
+///solid angle, for a point found at t along direction.
+pub(crate) fn area_pdf(t: f64, direction: &Vec3, normal: &Vec3, area: f64) -> f64{
+    let distance_squared = t*t*direction.length_squared();
+    let cosine = f64::abs(dot(direction, normal))/direction.length();
+    if cosine < 1e-8{
+        return 0.0;
+    }
+    distance_squared/(cosine*area)
+}
+
+///A parallelogram with one corner at q and edges u and v. Its outward normal is u x v,
+///and (u, v) run from 0 to 1 along the two edges.
+pub struct Quad{
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    area: f64,
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+impl Quad{
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Quad{
+        let n = cross(&u, &v);
+        let normal = unit_vector(&n);
+        let w = n/dot(&n, &n);
+        let area = n.length();
+        let bbox = Aabb::surrounding(&Aabb::from_points(q, q+u+v), &Aabb::from_points(q+u, q+v)).padded();
+        Quad{q, u, v, w, normal, area, material, bbox}
+    }
+}
+impl Hittable for Quad{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let t = intersect_plane(r, ray_t, &self.q, &self.normal)?;
+        let p = r.at(t);
+
+        //Coordinates of the hit point along the edges, found using w = n / (n . n).
+        let planar_hit = p-self.q;
+        let alpha = dot(&self.w, &cross(&planar_hit, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar_hit));
+        let unit = Interval::new(0.0, 1.0);
+        if !unit.contains(alpha) || !unit.contains(beta){
+            return None;
+        }
+        Some(HitRecord::generate(p, self.normal, t, alpha, beta, r, self.material.clone()))
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        match self.intersect(&Ray::new(*origin, *direction, 0.0), &NEAR_NON_NEG){
+            Some(record) => area_pdf(record.t, direction, &self.normal, self.area),
+            None => 0.0
+        }
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        self.q + self.u*rng.gen::<f64>() + self.v*rng.gen::<f64>() - *origin
+    }
+    fn is_emitter(&self) -> bool{
+        self.material.is_emissive()
+    }
+}
+
+///A flat disk facing along normal. Its u coordinate is the angle around the center and v the distance from it,
+///both scaled to [0, 1].
+pub struct Disk{
+    center: Vec3,
+    radius: f64,
+    basis: Onb,
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+impl Disk{
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Disk{
+        let basis = Onb::new(&normal);
+        let n = basis.w;
+        //The disk extends radius * sin(angle between the axis and the normal) along each axis.
+        let extent = Vec3::new(
+            radius*f64::sqrt(f64::max(1.0-n.x*n.x, 0.0)),
+            radius*f64::sqrt(f64::max(1.0-n.y*n.y, 0.0)),
+            radius*f64::sqrt(f64::max(1.0-n.z*n.z, 0.0))
+        );
+        let bbox = Aabb::from_points(center-extent, center+extent).padded();
+        Disk{center, radius, basis, material, bbox}
+    }
+}
+impl Hittable for Disk{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let t = intersect_plane(r, ray_t, &self.center, &self.basis.w)?;
+        let p = r.at(t);
+        let offset = p-self.center;
+        let distance = offset.length();
+        if distance > self.radius{
+            return None;
+        }
+        let angle = f64::atan2(dot(&offset, &self.basis.v), dot(&offset, &self.basis.u)) + PI;
+        Some(HitRecord::generate(p, self.basis.w, t, angle/(2.0*PI), distance/self.radius, r, self.material.clone()))
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        match self.intersect(&Ray::new(*origin, *direction, 0.0), &NEAR_NON_NEG){
+            Some(record) => area_pdf(record.t, direction, &self.basis.w, PI*self.radius*self.radius),
+            None => 0.0
+        }
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        let p = random_in_unit_disk(rng)*self.radius;
+        self.center + self.basis.u*p.x + self.basis.v*p.y - *origin
+    }
+    fn is_emitter(&self) -> bool{
+        self.material.is_emissive()
+    }
+}
+
+///An infinite plane through point, facing along normal. Its (u, v) coordinates repeat every unit of distance
+///along two axes in the plane. Planes cannot be sampled, so they are never emitters.
+pub struct Plane{
+    point: Vec3,
+    basis: Onb,
+    material: Arc<dyn Material>
+}
+impl Plane{
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Plane{
+        Plane{point, basis: Onb::new(&normal), material}
+    }
+}
+impl Hittable for Plane{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let t = intersect_plane(r, ray_t, &self.point, &self.basis.w)?;
+        let p = r.at(t);
+        let offset = p-self.point;
+        let (u, v) = (dot(&offset, &self.basis.u), dot(&offset, &self.basis.v));
+        Some(HitRecord::generate(p, self.basis.w, t, u-f64::floor(u), v-f64::floor(v), r, self.material.clone()))
+    }
+    ///Planes are unbounded, so they are kept outside bounding volume hierarchies.
+    fn bounding_box(&self) -> Aabb{
+        Aabb{x: UNIVERSE, y: UNIVERSE, z: UNIVERSE}
+    }
+}
+
+///An axis-aligned box made of six outward-facing quads.
+pub struct Cuboid{
+    sides: Vec<Quad>,
+    bbox: Aabb
+}
+impl Cuboid{
+    ///Returns the box with opposite corners a and b.
+    pub fn new(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Cuboid{
+        let min = Vec3::new(f64::min(a.x, b.x), f64::min(a.y, b.y), f64::min(a.z, b.z));
+        let max = Vec3::new(f64::max(a.x, b.x), f64::max(a.y, b.y), f64::max(a.z, b.z));
+        let dx = Vec3::new(max.x-min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y-min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z-min.z);
+
+        let sides = vec![
+            Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, material.clone()),
+            Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy, material.clone()),
+            Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy, material.clone()),
+            Quad::new(Vec3::new(min.x, min.y, min.z), dz, dy, material.clone()),
+            Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz, material.clone()),
+            Quad::new(Vec3::new(min.x, min.y, min.z), dx, dz, material)
+        ];
+        Cuboid{sides, bbox: Aabb::from_points(min, max).padded()}
+    }
+}
+impl Hittable for Cuboid{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let mut hit_record: Option<HitRecord> = None;
+        let mut nearest = ray_t.max;
+        for side in self.sides.iter(){
+            if let Some(record) = side.intersect(r, &Interval::new(ray_t.min, nearest)){
+                nearest = record.t;
+                hit_record = Some(record);
+            }
+        }
+        hit_record
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    ///Samples each side equally often.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        self.sides.iter().map(|side| side.pdf_value(origin, direction)).sum::<f64>()/self.sides.len() as f64
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        self.sides[rng.gen_range(0..self.sides.len())].random(origin, rng)
+    }
+    fn is_emitter(&self) -> bool{
+        self.sides[0].is_emitter()
+    }
+}
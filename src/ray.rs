@@ -1,13 +1,15 @@
 use crate::vec3::Vec3;
 pub struct Ray{
     pub origin: Vec3,
-    pub direction: Vec3
+    pub direction: Vec3,
+    ///The moment within the shutter interval at which the ray was sent, used to place moving objects.
+    pub time: f64
 }
 impl Ray{
-    pub fn new(origin: Vec3, direction: Vec3) -> Ray{
-        Ray{origin, direction}
+    pub fn new(origin: Vec3, direction: Vec3, time: f64) -> Ray{
+        Ray{origin, direction, time}
     }
     pub fn at(&self, t: f64) -> Vec3{
         self.origin+self.direction*t
     }
-}
\ No newline at end of file
+}
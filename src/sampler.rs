@@ -0,0 +1,39 @@
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+use crate::Ray;
+
+///The random number generator threaded through camera sampling, materials and textures.
+///A fixed algorithm is used so that a given seed produces the same image on every run and machine.
+pub type Sampler = Pcg64Mcg;
+
+///Returns a sampler for one pixel. Every pixel gets its own stream derived from the global seed,
+///so the result does not depend on which thread renders the pixel or in which order.
+pub fn pixel_sampler(seed: u64, i: i32, j: i32) -> Sampler{
+    let pixel = ((j as u32 as u64) << 32) | i as u32 as u64;
+    Sampler::seed_from_u64(splitmix64(splitmix64(seed) ^ pixel))
+}
+
+///Scrambles a 64-bit value so that nearby inputs give unrelated outputs.
+pub(crate) fn splitmix64(x: u64) -> u64{
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+///Returns a salt base for ray_random for the object with the given index in a scene rendered with seed.
+///Objects that hash the same ray, such as two overlapping media, add their own small salts to it, so that
+///different indices draw independent numbers while the same scene and seed always draw the same ones.
+pub fn object_salt(seed: u64, index: u64) -> u64{
+    (splitmix64(seed) ^ index) << 32
+}
+
+///Returns a number in [0, 1) determined by the ray alone, for code such as Hittable::intersect that has no
+///sampler. Rays come from sampled directions, so their hashes are as random as the samples, and still reproducible.
+pub fn ray_random(r: &Ray, salt: u64) -> f64{
+    let mut hash = splitmix64(salt);
+    for x in [r.origin.x, r.origin.y, r.origin.z, r.direction.x, r.direction.y, r.direction.z, r.time]{
+        hash = splitmix64(hash ^ x.to_bits());
+    }
+    (hash >> 11) as f64/(1u64 << 53) as f64
+}
@@ -1,14 +1,48 @@
-use crate::{Ray, Hittable, hittable::HitRecord, utils::Interval};
+use std::sync::Arc;
+use rand::Rng;
+use crate::{aabb::{Aabb, EMPTY_BOX}, background::Background, bvh::BvhNode, Ray, Hittable, hittable::HitRecord, light::Light, sampler::Sampler, utils::{Interval, NEAR_NON_NEG}, Vec3};
 pub struct Scene{
-    objects: Vec<Box<dyn Hittable>>
+    objects: Vec<Box<dyn Hittable>>,
+    ///Emitting objects that are also sampled directly at each bounce.
+    emitters: Vec<Arc<dyn Hittable>>,
+    ///Point, spot and directional lights, which have no surface and are only reached by shadow rays.
+    pub lights: Vec<Light>,
+    pub background: Background
 }
 impl Scene{
     pub fn new() -> Scene{
         let objects: Vec<Box<dyn Hittable>> = Vec::new();
-        return Scene{objects};
+        return Scene{objects, emitters: Vec::new(), lights: Vec::new(), background: Background::default()};
     }
+    ///Adds an object to the scene, and to the emitter list if it is an emitter.
     pub fn push(&mut self, obj: Box<dyn Hittable>){
-        self.objects.push(obj);
+        if obj.is_emitter(){
+            let emitter: Arc<dyn Hittable> = Arc::from(obj);
+            self.emitters.push(emitter.clone());
+            self.objects.push(Box::new(emitter));
+        }else{
+            self.objects.push(obj);
+        }
+    }
+    pub fn has_emitters(&self) -> bool{
+        !self.emitters.is_empty()
+    }
+    ///Returns the fraction of light passing along the segment from p along the unit direction for distance:
+    ///0 if something solid blocks it, and less than 1 if it passes through volumes.
+    pub fn visibility(&self, p: &Vec3, direction: &Vec3, distance: f64, time: f64) -> f64{
+        let shadow_ray = Ray::new(*p, *direction, time);
+        self.transmittance(&shadow_ray, &Interval::new(NEAR_NON_NEG.min, distance*(1.0-1e-6)))
+    }
+    ///Replaces the bounded objects in the scene with a bounding volume hierarchy over them.
+    ///Unbounded objects such as planes are left alongside it.
+    pub fn build_bvh(&mut self){
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = std::mem::take(&mut self.objects)
+            .into_iter()
+            .partition(|o| o.bounding_box().is_bounded());
+        self.objects = unbounded;
+        if !bounded.is_empty(){
+            self.objects.push(Box::new(BvhNode::new(bounded)));
+        }
     }
 }
 impl Hittable for Scene{
@@ -30,4 +64,32 @@ impl Hittable for Scene{
 
         return hit_record;
     }
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> f64{
+        let mut transmittance = 1.0;
+        for o in self.objects.iter(){
+            transmittance *= o.transmittance(r, ray_t);
+            if transmittance == 0.0{
+                break;
+            }
+        }
+        transmittance
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.objects.iter().fold(EMPTY_BOX, |bbox, o| Aabb::surrounding(&bbox, &o.bounding_box()))
+    }
+    ///Returns the density over solid angle with which random picks direction from origin.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        if self.emitters.is_empty(){
+            return 0.0;
+        }
+        let total: f64 = self.emitters.iter().map(|emitter| emitter.pdf_value(origin, direction)).sum();
+        total/self.emitters.len() as f64
+    }
+    ///Returns a direction from origin toward a random point on an emitter picked uniformly.
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        self.emitters[rng.gen_range(0..self.emitters.len())].random(origin, rng)
+    }
+    fn is_emitter(&self) -> bool{
+        self.has_emitters()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,943 @@
+//!Text scene descriptions in TOML.
+//!
+//!A scene file has an optional `[camera]` table whose keys match the fields of `CameraSettings`,
+//!an optional `[background]` table, a `[textures]` table of named textures, a `[materials]` table of
+//!named materials whose colors are either constants or texture names, a `[groups]` table of named
+//!object lists that `instance` objects place with a transform and `medium` objects fill with fog,
+//!an `[[objects]]` array referring to materials by name, including voxel grid `volume`s, and a
+//!`[[lights]]` array of point, spot and directional lights:
+//!
+//!```toml
+//![camera]
+//!look_from = [13.0, 2.0, 3.0]
+//!vfov = 20.0
+//!
+//![background]
+//!type = "solid"
+//!color = [0.0, 0.0, 0.0]
+//!
+//![materials.ground]
+//!type = "lambertian"
+//!albedo = [0.5, 0.5, 0.5]
+//!
+//![[objects]]
+//!type = "plane"
+//!point = [0.0, 0.0, 0.0]
+//!normal = [0.0, 1.0, 0.0]
+//!material = "ground"
+//!```
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, fs, path::Path, sync::Arc};
+use serde::Deserialize;
+use toml::Spanned;
+use crate::{
+    background::Background,
+    camera::CameraSettings,
+    environment::EnvironmentMap,
+    grid::Grid,
+    bvh::Group,
+    hittable::{Hittable, Sphere},
+    instance::Instance,
+    light::Light,
+    input,
+    material::{Conductor, Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal},
+    matrix::Quaternion,
+    medium::{ConstantMedium, GridMedium},
+    microfacet::metal_ior,
+    motion::{Keyframes, Pose},
+    obj::load_obj,
+    quad::{Cuboid, Disk, Plane, Quad},
+    sampler::object_salt,
+    scene::Scene,
+    sky::Sky,
+    texture::{CheckerTexture, ImageTexture, NoisePattern, NoiseTexture, SolidColor, Texture},
+    triangle::{MeshData, Triangle, TriangleMesh},
+    utils::degrees_to_radians,
+    vec3::{cross, Vec3}
+};
+
+///An error found while loading a scene file, with the line it was found on where known.
+#[derive(Debug)]
+pub struct SceneError{
+    pub line: Option<usize>,
+    pub message: String
+}
+impl SceneError{
+    pub(crate) fn on_line(line: usize, message: String) -> SceneError{
+        SceneError{line: Some(line), message}
+    }
+    fn at(source: &str, offset: usize, message: String) -> SceneError{
+        let line = source[..offset].matches('\n').count() + 1;
+        SceneError{line: Some(line), message}
+    }
+}
+impl Display for SceneError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line{
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+impl std::error::Error for SceneError{}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription{
+    #[serde(default)]
+    camera: CameraDescription,
+    background: Option<Spanned<BackgroundDescription>>,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDescription>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDescription>>,
+    ///Named lists of objects built once and placed any number of times by `instance` objects.
+    #[serde(default)]
+    groups: BTreeMap<String, Vec<Spanned<ObjectDescription>>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDescription>>,
+    #[serde(default)]
+    lights: Vec<Spanned<LightDescription>>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription{
+    aspect_ratio: Option<f64>,
+    image_width: Option<i32>,
+    image_height: Option<i32>,
+    vfov: Option<f64>,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<i32>,
+    seed: Option<u64>
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum BackgroundDescription{
+    Solid{color: [f64; 3]},
+    Gradient{horizon: [f64; 3], zenith: [f64; 3]},
+    ///An equirectangular image relative to the scene file, turned by rotation degrees around the y axis.
+    Environment{
+        path: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "BackgroundDescription::default_intensity")]
+        intensity: f64
+    },
+    ///The Preetham daylight sky. The sun's azimuth is measured clockwise from -z toward +x, and intensity scales
+    ///radiance given in kcd/m^2.
+    Sky{
+        elevation: f64,
+        #[serde(default)]
+        azimuth: f64,
+        #[serde(default = "BackgroundDescription::default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "BackgroundDescription::default_sky_intensity")]
+        intensity: f64
+    }
+}
+impl BackgroundDescription{
+    fn default_turbidity() -> f64{
+        3.0
+    }
+    fn default_sky_intensity() -> f64{
+        0.05
+    }
+    fn default_intensity() -> f64{
+        1.0
+    }
+}
+
+///Either a constant color or the name of an entry in the `[textures]` table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorOrTexture{
+    Color([f64; 3]),
+    Texture(String)
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription{
+    Solid{color: [f64; 3]},
+    Checker{scale: f64, even: ColorOrTexture, odd: ColorOrTexture},
+    ///An image file, relative to the scene file.
+    Image{path: String},
+    Perlin(NoiseDescription),
+    Fbm(NoiseDescription),
+    Turbulence(NoiseDescription),
+    Marble(NoiseDescription),
+    Wood(NoiseDescription),
+    Worley(NoiseDescription)
+}
+
+///Parameters shared by the procedural noise textures. Octaves are ignored by perlin and worley.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDescription{
+    #[serde(default = "NoiseDescription::default_scale")]
+    scale: f64,
+    #[serde(default)]
+    low: [f64; 3],
+    #[serde(default = "NoiseDescription::default_high")]
+    high: [f64; 3],
+    #[serde(default = "NoiseDescription::default_octaves")]
+    octaves: u32,
+    #[serde(default)]
+    seed: u64
+}
+impl NoiseDescription{
+    fn default_scale() -> f64{
+        1.0
+    }
+    fn default_high() -> [f64; 3]{
+        [1.0, 1.0, 1.0]
+    }
+    fn default_octaves() -> u32{
+        7
+    }
+    fn build(&self, pattern: NoisePattern) -> Arc<dyn Texture>{
+        Arc::new(NoiseTexture::new(pattern, self.scale, vec3(self.low), vec3(self.high), self.seed))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription{
+    Lambertian{albedo: ColorOrTexture},
+    Metal{albedo: ColorOrTexture, #[serde(default)] fuzz: f64},
+    ///Glass, colored by absorbing each color at a rate per unit distance traveled inside.
+    Dielectric{refraction_index: f64, #[serde(default)] absorption: [f64; 3]},
+    DiffuseLight{emit: [f64; 3]},
+    ///A rough metal, either gold, copper, aluminium or silver named by metal, or given by a complex index of
+    ///refraction eta + ik. Roughness and anisotropy are between 0 and 1.
+    Conductor{
+        metal: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64
+    },
+    ///Phase functions for volumes made by `medium` and `volume` objects, which accept no other materials.
+    Isotropic{albedo: ColorOrTexture},
+    HenyeyGreenstein{albedo: ColorOrTexture, g: f64}
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectDescription{
+    ///A sphere at center, or moving through keyframes instead.
+    Sphere{
+        center: Option<[f64; 3]>,
+        #[serde(default)]
+        keyframes: Vec<CenterKey>,
+        radius: f64,
+        material: String
+    },
+    Triangle{vertices: [[f64; 3]; 3], material: String},
+    ///A parallelogram with corner q and edges u and v.
+    Quad{q: [f64; 3], u: [f64; 3], v: [f64; 3], material: String},
+    Plane{point: [f64; 3], normal: [f64; 3], material: String},
+    Disk{center: [f64; 3], normal: [f64; 3], radius: f64, material: String},
+    ///An axis-aligned box between two opposite corners.
+    Box{min: [f64; 3], max: [f64; 3], material: String},
+    Mesh{
+        positions: Vec<[f64; 3]>,
+        #[serde(default)]
+        normals: Vec<[f64; 3]>,
+        #[serde(default)]
+        uvs: Vec<[f64; 2]>,
+        indices: Vec<[u32; 3]>,
+        material: String
+    },
+    ///A Wavefront OBJ file, relative to the scene file. Its MTL materials are used where present,
+    ///falling back to the named material.
+    Obj{path: String, material: String},
+    ///A copy of a group, scaled, then rotated by Euler angles in degrees around x, y and z, then translated.
+    ///Keyframes, when given, replace the fixed transform.
+    Instance{
+        group: String,
+        #[serde(flatten)]
+        transform: TransformDescription,
+        #[serde(default)]
+        keyframes: Vec<PoseKey>
+    },
+    ///A volume of constant density filling the closed shape of a group, scattering with a phase function material.
+    Medium{group: String, density: f64, material: String},
+    ///A voxel grid file, relative to the scene file, filling the axis-aligned box between two opposite corners.
+    ///Emission is the light given off wherever a ray collides with the volume.
+    Volume{
+        path: String,
+        min: [f64; 3],
+        max: [f64; 3],
+        #[serde(default = "ObjectDescription::default_scale")]
+        density_scale: f64,
+        #[serde(default = "ObjectDescription::default_scale")]
+        emission_scale: f64,
+        material: String
+    }
+}
+impl ObjectDescription{
+    fn default_scale() -> f64{
+        1.0
+    }
+}
+
+///Lights without a surface. Angles are in degrees.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum LightDescription{
+    Point{position: [f64; 3], intensity: [f64; 3]},
+    Spot{
+        position: [f64; 3],
+        direction: [f64; 3],
+        intensity: [f64; 3],
+        cone_angle: f64,
+        #[serde(default)]
+        falloff: f64
+    },
+    Directional{direction: [f64; 3], irradiance: [f64; 3]}
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CenterKey{
+    time: f64,
+    center: [f64; 3]
+}
+
+#[derive(Deserialize, Default)]
+struct TransformDescription{
+    translate: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    scale: Option<Scale>
+}
+impl TransformDescription{
+    fn is_empty(&self) -> bool{
+        self.translate.is_none() && self.rotate.is_none() && self.scale.is_none()
+    }
+    fn pose(&self) -> Pose{
+        let scale = match self.scale.as_ref().unwrap_or(&Scale::Uniform(1.0)){
+            Scale::Uniform(s) => Vec3::new(*s, *s, *s),
+            Scale::PerAxis(s) => vec3(*s)
+        };
+        Pose{
+            translation: vec3(self.translate.unwrap_or([0.0; 3])),
+            rotation: Quaternion::from_euler(vec3(self.rotate.unwrap_or([0.0; 3]).map(degrees_to_radians))),
+            scale
+        }
+    }
+}
+
+///The fields of TransformDescription at a time. They are spelled out rather than flattened,
+///since flattening would let misspelled keys through.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PoseKey{
+    time: f64,
+    translate: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    scale: Option<Scale>
+}
+impl PoseKey{
+    fn pose(&self) -> Pose{
+        TransformDescription{translate: self.translate, rotate: self.rotate, scale: self.scale}.pose()
+    }
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(untagged)]
+enum Scale{
+    Uniform(f64),
+    PerAxis([f64; 3])
+}
+
+fn vec3(v: [f64; 3]) -> Vec3{
+    Vec3::new(v[0], v[1], v[2])
+}
+
+///Builds textures on first use, so they can refer to each other in any order.
+struct TextureLibrary<'a>{
+    source: &'a str,
+    base_dir: &'a Path,
+    descriptions: &'a BTreeMap<String, Spanned<TextureDescription>>,
+    built: BTreeMap<&'a str, Arc<dyn Texture>>,
+    in_progress: Vec<&'a str>
+}
+impl<'a> TextureLibrary<'a>{
+    fn resolve(&mut self, texture: &'a ColorOrTexture) -> Result<Arc<dyn Texture>, SceneError>{
+        match texture{
+            ColorOrTexture::Color(color) => Ok(Arc::new(SolidColor::new(vec3(*color)))),
+            ColorOrTexture::Texture(name) => self.get(name)
+        }
+    }
+    fn get(&mut self, name: &'a str) -> Result<Arc<dyn Texture>, SceneError>{
+        if let Some(texture) = self.built.get(name){
+            return Ok(texture.clone());
+        }
+        let Some((name, description)) = self.descriptions.get_key_value(name) else {
+            return Err(SceneError{line: None, message: format!("unknown texture `{}`", name)});
+        };
+        let error = |message: String| SceneError::at(self.source, description.span().start, format!("texture `{}`: {}", name, message));
+        //Errors from textures this one refers to already carry their own line.
+        let nested = |e: SceneError| if e.line.is_some() {e} else {error(e.message)};
+        if self.in_progress.contains(&name.as_str()){
+            return Err(error("texture refers to itself".to_string()));
+        }
+
+        self.in_progress.push(name);
+        let texture: Arc<dyn Texture> = match description.get_ref(){
+            TextureDescription::Solid{color} => Arc::new(SolidColor::new(vec3(*color))),
+            TextureDescription::Checker{scale, even, odd} => {
+                if *scale <= 0.0{
+                    return Err(error("scale must be positive".to_string()));
+                }
+                let even = self.resolve(even).map_err(nested)?;
+                let odd = self.resolve(odd).map_err(nested)?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDescription::Image{path} => {
+                let image = input::load(&self.base_dir.join(path)).map_err(|e| error(e.to_string()))?;
+                Arc::new(ImageTexture::new(image))
+            }
+            TextureDescription::Perlin(noise) => noise.build(NoisePattern::Perlin),
+            TextureDescription::Fbm(noise) => noise.build(NoisePattern::Fbm{octaves: noise.octaves}),
+            TextureDescription::Turbulence(noise) => noise.build(NoisePattern::Turbulence{octaves: noise.octaves}),
+            TextureDescription::Marble(noise) => noise.build(NoisePattern::Marble{octaves: noise.octaves}),
+            TextureDescription::Wood(noise) => noise.build(NoisePattern::Wood{octaves: noise.octaves}),
+            TextureDescription::Worley(noise) => noise.build(NoisePattern::Worley)
+        };
+        self.in_progress.pop();
+
+        self.built.insert(name, texture.clone());
+        Ok(texture)
+    }
+}
+
+///Reads a scene file and builds the scene and the camera settings it describes.
+///Camera parameters missing from the file keep their default values.
+pub fn load_scene(path: &Path) -> Result<(Scene, CameraSettings), SceneError>{
+    let source = fs::read_to_string(path).map_err(|e| SceneError{
+        line: None,
+        message: format!("could not read {}: {}", path.display(), e)
+    })?;
+    parse_scene(&source, path.parent().unwrap_or(Path::new("")))
+}
+
+///Builds the scene and camera settings described by the TOML source.
+///Files referenced by the scene are resolved relative to base_dir.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<(Scene, CameraSettings), SceneError>{
+    let description: SceneDescription = toml::from_str(source).map_err(|e| match e.span(){
+        Some(span) => SceneError::at(source, span.start, e.message().to_string()),
+        None => SceneError{line: None, message: e.message().to_string()}
+    })?;
+
+    let camera = build_camera(&description.camera);
+
+    let mut textures = TextureLibrary{
+        source,
+        base_dir,
+        descriptions: &description.textures,
+        built: BTreeMap::new(),
+        in_progress: Vec::new()
+    };
+    for name in description.textures.keys(){
+        textures.get(name)?;
+    }
+    let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+    for (name, material) in description.materials.iter(){
+        let built = build_material(material.get_ref(), &mut textures)
+            .map_err(|message| SceneError::at(source, material.span().start, format!("material `{}`: {}", name, message)))?;
+        materials.insert(name, built);
+    }
+    let phase_functions: BTreeSet<&str> = description.materials.iter()
+        .filter(|(_, material)| matches!(material.get_ref(), MaterialDescription::Isotropic{..} | MaterialDescription::HenyeyGreenstein{..}))
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    let mut scene = Scene::new();
+    if let Some(background) = &description.background{
+        scene.background = match background.get_ref(){
+            BackgroundDescription::Solid{color} => Background::Solid(vec3(*color)),
+            BackgroundDescription::Gradient{horizon, zenith} => Background::Gradient{horizon: vec3(*horizon), zenith: vec3(*zenith)},
+            BackgroundDescription::Environment{path, rotation, intensity} => {
+                let image = input::load(&base_dir.join(path))
+                    .map_err(|e| SceneError::at(source, background.span().start, format!("background: {}", e)))?;
+                Background::Environment(EnvironmentMap::new(image, *rotation, *intensity))
+            }
+            BackgroundDescription::Sky{elevation, azimuth, turbidity, intensity} => {
+                let error = |message: &str| SceneError::at(source, background.span().start, format!("background: {}", message));
+                if !(0.0..=90.0).contains(elevation){
+                    return Err(error("sun elevation must be between 0 and 90 degrees"));
+                }
+                if !(1.7..=10.0).contains(turbidity){
+                    return Err(error("turbidity must be between 1.7 and 10"));
+                }
+                Background::Sky(Sky::new(*elevation, *azimuth, *turbidity, *intensity))
+            }
+        };
+    }
+    let mut groups = GroupLibrary{
+        source,
+        base_dir,
+        materials: &materials,
+        phase_functions: &phase_functions,
+        seed: camera.seed,
+        media: 0,
+        descriptions: &description.groups,
+        built: BTreeMap::new(),
+        in_progress: Vec::new()
+    };
+    let mut objects = Vec::new();
+    for object in description.objects.iter(){
+        groups.build_object(object, &mut objects)?;
+    }
+    for object in objects{
+        scene.push(object);
+    }
+    for light in description.lights.iter(){
+        let built = build_light(light.get_ref()).map_err(|message| SceneError::at(source, light.span().start, message))?;
+        scene.lights.push(built);
+    }
+    scene.build_bvh();
+
+    Ok((scene, camera))
+}
+
+///Builds groups on first use, so they can place each other in any order.
+struct GroupLibrary<'a>{
+    source: &'a str,
+    base_dir: &'a Path,
+    materials: &'a BTreeMap<&'a str, Arc<dyn Material>>,
+    ///The names of the materials that media may use.
+    phase_functions: &'a BTreeSet<&'a str>,
+    ///The scene's seed and the number of media built so far, from which each medium's salt is made.
+    seed: u64,
+    media: u64,
+    descriptions: &'a BTreeMap<String, Vec<Spanned<ObjectDescription>>>,
+    built: BTreeMap<&'a str, Arc<dyn Hittable>>,
+    in_progress: Vec<&'a str>
+}
+impl<'a> GroupLibrary<'a>{
+    fn get(&mut self, name: &'a str) -> Result<Arc<dyn Hittable>, SceneError>{
+        if let Some(group) = self.built.get(name){
+            return Ok(group.clone());
+        }
+        let Some((name, descriptions)) = self.descriptions.get_key_value(name) else {
+            return Err(SceneError{line: None, message: format!("unknown group `{}`", name)});
+        };
+        if self.in_progress.contains(&name.as_str()){
+            return Err(SceneError{line: None, message: format!("group `{}` places itself", name)});
+        }
+
+        self.in_progress.push(name);
+        let mut objects = Vec::new();
+        for object in descriptions.iter(){
+            self.build_object(object, &mut objects)?;
+        }
+        self.in_progress.pop();
+        if objects.is_empty(){
+            return Err(SceneError{line: None, message: format!("group `{}` is empty", name)});
+        }
+
+        let group: Arc<dyn Hittable> = Arc::new(Group::new(objects));
+        self.built.insert(name, group.clone());
+        Ok(group)
+    }
+    ///Returns a salt for the next medium, which depends only on the scene description.
+    fn next_salt(&mut self) -> u64{
+        self.media += 1;
+        object_salt(self.seed, self.media)
+    }
+    ///Builds the objects described by object, adding them to objects.
+    fn build_object(&mut self, object: &'a Spanned<ObjectDescription>, objects: &mut Vec<Box<dyn Hittable>>) -> Result<(), SceneError>{
+        let (source, base_dir, materials, phase_functions) = (self.source, self.base_dir, self.materials, self.phase_functions);
+        let error = |message: String| SceneError::at(source, object.span().start, message);
+        //Errors from groups this object places already carry their own line.
+        let nested = |e: SceneError| if e.line.is_some() {e} else {error(e.message)};
+        let lookup = |name: &str| materials.get(name).cloned().ok_or_else(|| error(format!("unknown material `{}`", name)));
+        let phase_function = |name: &str| if materials.contains_key(name) && !phase_functions.contains(name){
+            Err(error(format!("material `{}` is not a phase function; media need an isotropic or henyey_greenstein material", name)))
+        }else{
+            lookup(name)
+        };
+        match object.get_ref(){
+            ObjectDescription::Sphere{center, keyframes, radius, material} => {
+                if *radius <= 0.0{
+                    return Err(error("sphere radius must be positive".to_string()));
+                }
+                let center = match (center, keyframes.is_empty()){
+                    (Some(center), true) => Keyframes::constant(vec3(*center)),
+                    (None, false) => Keyframes::new(keyframes.iter().map(|k| (k.time, vec3(k.center))).collect()).unwrap(),
+                    _ => return Err(error("sphere needs either a center or keyframes".to_string()))
+                };
+                objects.push(Box::new(Sphere::moving(center, *radius, lookup(material)?)));
+            }
+            ObjectDescription::Triangle{vertices, material} => {
+                let [p0, p1, p2] = vertices.map(vec3);
+                objects.push(Box::new(Triangle::new(p0, p1, p2, lookup(material)?)));
+            }
+            ObjectDescription::Quad{q, u, v, material} => {
+                let (u, v) = (vec3(*u), vec3(*v));
+                if cross(&u, &v).near_zero(){
+                    return Err(error("quad edges must not be parallel".to_string()));
+                }
+                objects.push(Box::new(Quad::new(vec3(*q), u, v, lookup(material)?)));
+            }
+            ObjectDescription::Plane{point, normal, material} => {
+                if vec3(*normal).near_zero(){
+                    return Err(error("plane normal must not be zero".to_string()));
+                }
+                //An unbounded surface cannot be sampled as a light.
+                let material = lookup(material)?;
+                if material.is_emissive(){
+                    return Err(error("planes cannot be emissive; use a quad or disk for area lights".to_string()));
+                }
+                objects.push(Box::new(Plane::new(vec3(*point), vec3(*normal), material)));
+            }
+            ObjectDescription::Disk{center, normal, radius, material} => {
+                if vec3(*normal).near_zero(){
+                    return Err(error("disk normal must not be zero".to_string()));
+                }
+                if *radius <= 0.0{
+                    return Err(error("disk radius must be positive".to_string()));
+                }
+                objects.push(Box::new(Disk::new(vec3(*center), vec3(*normal), *radius, lookup(material)?)));
+            }
+            ObjectDescription::Box{min, max, material} => {
+                if (0..3).any(|i| min[i] >= max[i]){
+                    return Err(error("box max must be greater than min along every axis".to_string()));
+                }
+                objects.push(Box::new(Cuboid::new(vec3(*min), vec3(*max), lookup(material)?)));
+            }
+            ObjectDescription::Mesh{positions, normals, uvs, indices, material} => {
+                if !normals.is_empty() && normals.len() != positions.len(){
+                    return Err(error("mesh must have one normal per position".to_string()));
+                }
+                if !uvs.is_empty() && uvs.len() != positions.len(){
+                    return Err(error("mesh must have one uv per position".to_string()));
+                }
+                if indices.iter().flatten().any(|i| *i as usize >= positions.len()){
+                    return Err(error("mesh index out of range".to_string()));
+                }
+                objects.push(Box::new(TriangleMesh::new(MeshData{
+                    positions: positions.iter().copied().map(vec3).collect(),
+                    normals: normals.iter().copied().map(vec3).collect(),
+                    uvs: uvs.clone(),
+                    indices: indices.clone(),
+                    material: lookup(material)?
+                })));
+            }
+            ObjectDescription::Obj{path, material} => {
+                let meshes = load_obj(&base_dir.join(path), lookup(material)?)
+                    .map_err(|e| error(e.to_string()))?;
+                for mesh in meshes{
+                    objects.push(Box::new(mesh));
+                }
+            }
+            ObjectDescription::Instance{group, transform, keyframes} => {
+                let object = self.get(group).map_err(nested)?;
+                let instance = if keyframes.is_empty(){
+                    Instance::new(object, transform.pose().matrix())
+                }else if transform.is_empty(){
+                    let poses = keyframes.iter().map(|k| (k.time, k.pose())).collect();
+                    Instance::animated(object, Keyframes::new(poses).unwrap())
+                }else{
+                    return Err(error("instance needs either a transform or keyframes, not both".to_string()));
+                };
+                objects.push(Box::new(instance.ok_or_else(|| error("instance transform must not be singular, nor flip the sign of a scale between keyframes".to_string()))?));
+            }
+            ObjectDescription::Medium{group, density, material} => {
+                let boundary = self.get(group).map_err(nested)?;
+                if *density <= 0.0{
+                    return Err(error("medium density must be positive".to_string()));
+                }
+                let salt = self.next_salt();
+                objects.push(Box::new(ConstantMedium::new(boundary, *density, phase_function(material)?, salt)));
+            }
+            ObjectDescription::Volume{path, min, max, density_scale, emission_scale, material} => {
+                let (min, max) = (vec3(*min), vec3(*max));
+                if min.x >= max.x || min.y >= max.y || min.z >= max.z{
+                    return Err(error("volume max must be greater than min along every axis".to_string()));
+                }
+                if *density_scale < 0.0 || *emission_scale < 0.0{
+                    return Err(error("volume scales must not be negative".to_string()));
+                }
+                let grid = Grid::read(&base_dir.join(path)).map_err(|e| error(e.to_string()))?;
+                let salt = self.next_salt();
+                objects.push(Box::new(GridMedium::new(grid, min, max, *density_scale, *emission_scale, phase_function(material)?, salt)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn build_light(description: &LightDescription) -> Result<Light, String>{
+    match description{
+        LightDescription::Point{position, intensity} => Ok(Light::Point{position: vec3(*position), intensity: vec3(*intensity)}),
+        LightDescription::Spot{position, direction, intensity, cone_angle, falloff} => {
+            if vec3(*direction).near_zero(){
+                return Err("spot light direction must not be zero".to_string());
+            }
+            if *cone_angle <= 0.0 || *cone_angle >= 180.0{
+                return Err("spot light cone_angle must be between 0 and 180 degrees".to_string());
+            }
+            if *falloff < 0.0{
+                return Err("spot light falloff must not be negative".to_string());
+            }
+            Ok(Light::Spot{
+                position: vec3(*position),
+                direction: vec3(*direction),
+                intensity: vec3(*intensity),
+                cone_angle: *cone_angle,
+                falloff: *falloff
+            })
+        }
+        LightDescription::Directional{direction, irradiance} => {
+            if vec3(*direction).near_zero(){
+                return Err("directional light direction must not be zero".to_string());
+            }
+            Ok(Light::Directional{direction: vec3(*direction), irradiance: vec3(*irradiance)})
+        }
+    }
+}
+
+fn build_camera(description: &CameraDescription) -> CameraSettings{
+    let defaults = CameraSettings::default();
+    CameraSettings{
+        aspect_ratio: description.aspect_ratio.unwrap_or(defaults.aspect_ratio),
+        image_width: description.image_width.unwrap_or(defaults.image_width),
+        image_height: description.image_height.or(defaults.image_height),
+        vfov: description.vfov.unwrap_or(defaults.vfov),
+        look_from: description.look_from.map_or(defaults.look_from, vec3),
+        look_at: description.look_at.map_or(defaults.look_at, vec3),
+        vup: description.vup.map_or(defaults.vup, vec3),
+        defocus_angle: description.defocus_angle.unwrap_or(defaults.defocus_angle),
+        focus_dist: description.focus_dist.unwrap_or(defaults.focus_dist),
+        shutter_open: description.shutter_open.unwrap_or(defaults.shutter_open),
+        shutter_close: description.shutter_close.unwrap_or(defaults.shutter_close),
+        samples_per_pixel: description.samples_per_pixel.unwrap_or(defaults.samples_per_pixel),
+        max_depth: description.max_depth.unwrap_or(defaults.max_depth),
+        seed: description.seed.unwrap_or(defaults.seed)
+    }
+}
+
+fn build_material<'a>(description: &'a MaterialDescription, textures: &mut TextureLibrary<'a>) -> Result<Arc<dyn Material>, String>{
+    match description{
+        MaterialDescription::Lambertian{albedo} => Ok(Arc::new(Lambertian::from_texture(textures.resolve(albedo).map_err(|e| e.message)?))),
+        MaterialDescription::Metal{albedo, fuzz} => {
+            if !(0.0..=1.0).contains(fuzz){
+                return Err("fuzz must be between 0 and 1".to_string());
+            }
+            Ok(Arc::new(Metal::from_texture(textures.resolve(albedo).map_err(|e| e.message)?, *fuzz)))
+        }
+        MaterialDescription::Dielectric{refraction_index, absorption} => {
+            if absorption.iter().any(|a| *a < 0.0){
+                return Err("dielectric absorption must not be negative".to_string());
+            }
+            Ok(Arc::new(Dielectric::absorbing(*refraction_index, vec3(*absorption))))
+        }
+        MaterialDescription::DiffuseLight{emit} => Ok(Arc::new(DiffuseLight::new(vec3(*emit)))),
+        MaterialDescription::Conductor{metal, eta, k, roughness, anisotropy} => {
+            if !(0.0..=1.0).contains(roughness) || !(0.0..=1.0).contains(anisotropy){
+                return Err("roughness and anisotropy must be between 0 and 1".to_string());
+            }
+            let (eta, k) = match (metal, eta, k){
+                (Some(metal), None, None) => metal_ior(metal).ok_or_else(|| format!("unknown metal `{}`", metal))?,
+                (None, Some(eta), Some(k)) => (vec3(*eta), vec3(*k)),
+                _ => return Err("conductor needs either metal or both eta and k".to_string())
+            };
+            Ok(Arc::new(Conductor::new(eta, k, *roughness, *anisotropy)))
+        }
+        MaterialDescription::Isotropic{albedo} => Ok(Arc::new(Isotropic::from_texture(textures.resolve(albedo).map_err(|e| e.message)?))),
+        MaterialDescription::HenyeyGreenstein{albedo, g} => {
+            if *g <= -1.0 || *g >= 1.0{
+                return Err("g must be between -1 and 1".to_string());
+            }
+            Ok(Arc::new(HenyeyGreenstein::from_texture(textures.resolve(albedo).map_err(|e| e.message)?, *g)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{utils::Interval, Ray};
+    use super::*;
+
+    fn parse(source: &str) -> Result<(Scene, CameraSettings), SceneError>{
+        parse_scene(source, Path::new(""))
+    }
+    fn error(source: &str) -> SceneError{
+        match parse(source){
+            Ok(_) => panic!("scene should not load"),
+            Err(e) => e
+        }
+    }
+
+    const SPHERE: &str = r#"
+[camera]
+image_width = 64
+look_from = [0.0, 0.0, 5.0]
+
+[materials.red]
+type = "lambertian"
+albedo = [1.0, 0.0, 0.0]
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "red"
+"#;
+
+    #[test]
+    fn loads_objects_and_camera(){
+        let (scene, camera) = parse(SPHERE).unwrap();
+        assert_eq!(camera.image_width, 64);
+        assert_eq!(camera.look_from.z, 5.0);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let record = scene.intersect(&r, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((record.t-4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_file_uses_defaults(){
+        let (_, camera) = parse("").unwrap();
+        assert_eq!(camera.image_width, CameraSettings::default().image_width);
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_their_line(){
+        let e = error("[camera]\nimage_width = 64\nvfov = \n");
+        assert_eq!(e.line, Some(3));
+    }
+
+    #[test]
+    fn rejects_unknown_keys(){
+        let e = error(&SPHERE.replace("radius = 1.0", "radius = 1.0\ncolour = 2"));
+        assert_eq!(e.line, Some(10));
+        assert!(e.message.contains("colour"));
+        let e = error("[camera]\nfov = 40.0\n");
+        assert!(e.message.contains("fov"));
+    }
+
+    #[test]
+    fn rejects_unknown_types(){
+        let e = error(&SPHERE.replace("\"lambertian\"", "\"velvet\""));
+        assert_eq!(e.line, Some(7));
+    }
+
+    #[test]
+    fn reports_unknown_materials_on_the_object_line(){
+        let e = error(&SPHERE.replace("material = \"red\"", "material = \"blue\""));
+        assert_eq!(e.line, Some(10));
+        assert!(e.message.contains("unknown material `blue`"));
+    }
+
+    #[test]
+    fn rejects_invalid_values(){
+        let e = error(&SPHERE.replace("radius = 1.0", "radius = -1.0"));
+        assert_eq!(e.line, Some(10));
+        assert!(e.message.contains("radius"));
+    }
+
+    #[test]
+    fn rejects_flat_boxes(){
+        let source = SPHERE.replace("type = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0", "type = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 0.0, 1.0]");
+        let e = error(&source);
+        assert_eq!(e.line, Some(10));
+        assert!(e.message.contains("box max must be greater than min"));
+        assert!(parse(&source.replace("max = [1.0, 0.0, 1.0]", "max = [1.0, 1.0, 1.0]")).is_ok());
+    }
+
+    #[test]
+    fn media_need_phase_functions(){
+        let medium = |material: &str| format!(r#"{}
+[materials.fog]
+type = "isotropic"
+albedo = [0.9, 0.9, 0.9]
+
+[[groups.ball]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "red"
+
+[[objects]]
+type = "medium"
+group = "ball"
+density = 0.5
+material = "{}"
+"#, SPHERE, material);
+        assert!(parse(&medium("fog")).is_ok());
+        let e = error(&medium("red"));
+        assert_eq!(e.line, Some(26));
+        assert!(e.message.contains("not a phase function"), "{}", e);
+        assert!(error(&medium("smoke")).message.contains("unknown material `smoke`"));
+    }
+
+    #[test]
+    fn renders_media_the_same_every_time(){
+        let fog = SPHERE.replace("image_width = 64", "image_width = 8\nsamples_per_pixel = 4") + r#"
+[materials.fog]
+type = "isotropic"
+albedo = [0.9, 0.9, 0.9]
+
+[[groups.ball]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.5
+material = "red"
+
+[[objects]]
+type = "medium"
+group = "ball"
+density = 0.5
+material = "fog"
+"#;
+        let render = || {
+            let (scene, camera) = parse(&fog).unwrap();
+            camera.build().render(&scene)
+        };
+        let (first, second) = (render(), render());
+        assert!(first.pixels().iter().zip(second.pixels()).all(|(a, b)| a.x == b.x && a.y == b.y && a.z == b.z));
+    }
+
+    #[test]
+    fn samples_instanced_lights(){
+        let lamp = |object: &str| format!(r#"{}
+[materials.glow]
+type = "diffuse_light"
+emit = [4.0, 4.0, 4.0]
+
+[[groups.lamp]]
+type = "quad"
+q = [0.0, 0.0, 0.0]
+u = [1.0, 0.0, 0.0]
+v = [0.0, 1.0, 0.0]
+material = "glow"
+
+[[objects]]
+{}
+"#, SPHERE, object);
+        let (scene, _) = parse(&lamp("type = \"instance\"\ngroup = \"lamp\"\ntranslate = [0.0, 3.0, 0.0]")).unwrap();
+        assert!(scene.has_emitters());
+        let e = error(&lamp("type = \"plane\"\npoint = [0.0, -1.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\nmaterial = \"glow\""));
+        assert_eq!(e.line, Some(27));
+        assert!(e.message.contains("planes cannot be emissive"));
+    }
+
+    #[test]
+    fn rejects_missing_files(){
+        assert!(load_scene(Path::new("no/such/scene.toml")).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use rand::Rng;
+use crate::{
+    onb::Onb,
+    sampler::Sampler,
+    utils::{degrees_to_radians, PI},
+    vec3::{dot, random_unit_vector, unit_vector, Vec3}
+};
+
+///Angular radius of the sun's disk as seen from the earth, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+///Luminance of the sun above the atmosphere, in the sky model's units of kcd/m^2.
+const SUN_LUMINANCE: f64 = 1.96e6;
+
+///Coefficients of the Perez distribution function for one of Y, x or y.
+struct Perez{
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64
+}
+impl Perez{
+    ///Returns the relative value at view zenith angle theta and angle gamma from the sun.
+    fn f(&self, cos_theta: f64, gamma: f64) -> f64{
+        let cos_gamma = f64::cos(gamma);
+        (1.0 + self.a*f64::exp(self.b/cos_theta)) * (1.0 + self.c*f64::exp(self.d*gamma) + self.e*cos_gamma*cos_gamma)
+    }
+}
+
+///Converts CIE xyY chromaticity and luminance to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3{
+    let big_x = x/y*luminance;
+    let big_z = (1.0-x-y)/y*luminance;
+    Vec3::new(
+        3.2406*big_x - 1.5372*luminance - 0.4986*big_z,
+        -0.9689*big_x + 1.8758*luminance + 0.0415*big_z,
+        0.0557*big_x - 0.2040*luminance + 1.0570*big_z
+    )
+}
+
+///Returns the fraction of sunlight at wavelength (in micrometers) that passes through the atmosphere
+///along a path with relative optical mass, from Rayleigh and aerosol scattering.
+fn transmittance(wavelength: f64, turbidity: f64, optical_mass: f64) -> f64{
+    let rayleigh = f64::exp(-0.008735*f64::powf(wavelength, -4.08)*optical_mass);
+    let beta = 0.04608*turbidity - 0.04586;
+    let aerosol = f64::exp(-beta*f64::powf(wavelength, -1.3)*optical_mass);
+    rayleigh*aerosol
+}
+
+///The Preetham analytic daylight sky, together with the sun's disk.
+///Radiance is given in kcd/m^2 multiplied by intensity.
+pub struct Sky{
+    ///Unit direction toward the sun.
+    sun: Vec3,
+    sun_zenith: f64,
+    sun_radiance: Vec3,
+    sun_cos_max: f64,
+    intensity: f64,
+    luminance: Perez,
+    x: Perez,
+    y: Perez,
+    zenith: (f64, f64, f64)
+}
+impl Sky{
+    ///Returns the sky with the sun elevation degrees above the horizon and azimuth degrees clockwise from -z
+    ///toward +x, under the given turbidity (2 for a very clear sky, up to about 10 for haze).
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, intensity: f64) -> Sky{
+        let (elevation, azimuth) = (degrees_to_radians(elevation), degrees_to_radians(azimuth));
+        let sun = Vec3::new(f64::sin(azimuth)*f64::cos(elevation), f64::sin(elevation), -f64::cos(azimuth)*f64::cos(elevation));
+        let theta_s = PI/2.0 - elevation;
+        let t = turbidity;
+
+        let chi = (4.0/9.0 - t/120.0)*(PI - 2.0*theta_s);
+        let zenith_luminance = (4.0453*t - 4.9710)*f64::tan(chi) - 0.2155*t + 2.4192;
+        let theta = [theta_s*theta_s*theta_s, theta_s*theta_s, theta_s, 1.0];
+        let chromaticity = |m: [[f64; 4]; 3]| -> f64{
+            let row = |r: [f64; 4]| r.iter().zip(theta.iter()).map(|(a, b)| a*b).sum::<f64>();
+            t*t*row(m[0]) + t*row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886]
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688]
+        ]);
+
+        //Sunlight is attenuated along a path through the air that grows longer toward the horizon.
+        let optical_mass = 1.0/(f64::cos(theta_s) + 0.15*f64::powf(93.885 - theta_s.to_degrees(), -1.253));
+        let sun_color = Vec3::new(
+            transmittance(0.68, t, optical_mass),
+            transmittance(0.55, t, optical_mass),
+            transmittance(0.44, t, optical_mass)
+        );
+
+        Sky{
+            sun,
+            sun_zenith: theta_s,
+            sun_radiance: sun_color*SUN_LUMINANCE,
+            sun_cos_max: f64::cos(SUN_ANGULAR_RADIUS),
+            intensity,
+            luminance: Perez{a: 0.1787*t - 1.4630, b: -0.3554*t + 0.4275, c: -0.0227*t + 5.3251, d: 0.1206*t - 2.5771, e: -0.0670*t + 0.3703},
+            x: Perez{a: -0.0193*t - 0.2592, b: -0.0665*t + 0.0008, c: -0.0004*t + 0.2125, d: -0.0641*t - 0.8989, e: -0.0033*t + 0.0452},
+            y: Perez{a: -0.0167*t - 0.2608, b: -0.0950*t + 0.0092, c: -0.0079*t + 0.2102, d: -0.0441*t - 1.6537, e: -0.0109*t + 0.0529},
+            zenith: (zenith_luminance, zenith_x, zenith_y)
+        }
+    }
+    ///Returns the sky's radiance alone, without the sun's disk.
+    fn sky_color(&self, d: &Vec3) -> Vec3{
+        //The model is only defined above the horizon, so lower directions see the horizon's color.
+        let cos_theta = f64::max(d.y, 0.001);
+        let gamma = f64::acos(f64::clamp(dot(d, &self.sun), -1.0, 1.0));
+        let relative = |perez: &Perez| perez.f(cos_theta, gamma)/perez.f(1.0, self.sun_zenith);
+        let (zenith_luminance, zenith_x, zenith_y) = self.zenith;
+        let color = xyy_to_rgb(zenith_x*relative(&self.x), zenith_y*relative(&self.y), zenith_luminance*relative(&self.luminance));
+        Vec3::new(f64::max(color.x, 0.0), f64::max(color.y, 0.0), f64::max(color.z, 0.0))
+    }
+    pub fn color(&self, direction: &Vec3) -> Vec3{
+        let d = unit_vector(direction);
+        let sky = self.sky_color(&d);
+        if dot(&d, &self.sun) >= self.sun_cos_max{
+            (sky + self.sun_radiance)*self.intensity
+        }else{
+            sky*self.intensity
+        }
+    }
+    ///Returns the density over solid angle with which sample picks direction: half of the samples aim at
+    ///the sun's disk and half are spread over the whole sphere.
+    pub fn pdf(&self, direction: &Vec3) -> f64{
+        let uniform = 1.0/(4.0*PI);
+        if dot(&unit_vector(direction), &self.sun) >= self.sun_cos_max{
+            0.5*uniform + 0.5/(2.0*PI*(1.0-self.sun_cos_max))
+        }else{
+            0.5*uniform
+        }
+    }
+    pub fn sample(&self, rng: &mut Sampler) -> Vec3{
+        if rng.gen::<f64>() >= 0.5{
+            return random_unit_vector(rng);
+        }
+        let z = 1.0 + rng.gen::<f64>()*(self.sun_cos_max-1.0);
+        let phi = 2.0*PI*rng.gen::<f64>();
+        let sin_theta = f64::sqrt(1.0-z*z);
+        Onb::new(&self.sun).transform(&Vec3::new(f64::cos(phi)*sin_theta, f64::sin(phi)*sin_theta, z))
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use crate::{image::Image, noise::{Perlin, Worley}, utils::Interval, vec3::Vec3};
+
+///A color that varies over a surface, looked up by surface coordinates (u, v) and hit point p.
+pub trait Texture: Send + Sync{
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3;
+}
+
+pub struct SolidColor{
+    albedo: Vec3
+}
+impl SolidColor{
+    pub fn new(albedo: Vec3) -> SolidColor{
+        SolidColor{albedo}
+    }
+}
+impl Texture for SolidColor{
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Vec3{
+        self.albedo
+    }
+}
+
+///A 3D checkerboard of cubes with the given edge length, alternating between two textures.
+pub struct CheckerTexture{
+    inverse_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>
+}
+impl CheckerTexture{
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture{
+        CheckerTexture{inverse_scale: 1.0/scale, even, odd}
+    }
+    pub fn from_colors(scale: f64, even: Vec3, odd: Vec3) -> CheckerTexture{
+        CheckerTexture::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+    }
+}
+impl Texture for CheckerTexture{
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3{
+        let x = f64::floor(self.inverse_scale*p.x) as i64;
+        let y = f64::floor(self.inverse_scale*p.y) as i64;
+        let z = f64::floor(self.inverse_scale*p.z) as i64;
+        if (x+y+z).rem_euclid(2) == 0{
+            self.even.value(u, v, p)
+        }else{
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+///An image mapped onto the surface with (0, 0) at the bottom left corner and (1, 1) at the top right.
+pub struct ImageTexture{
+    image: Image
+}
+impl ImageTexture{
+    pub fn new(image: Image) -> ImageTexture{
+        ImageTexture{image}
+    }
+}
+impl Texture for ImageTexture{
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3{
+        if self.image.width() <= 0 || self.image.height() <= 0{
+            return Vec3::new(0.0, 1.0, 1.0);
+        }
+        let unit = Interval::new(0.0, 1.0);
+        let u = unit.clamp(u);
+        let v = 1.0 - unit.clamp(v);
+
+        let i = i32::min((u*self.image.width() as f64) as i32, self.image.width()-1);
+        let j = i32::min((v*self.image.height() as f64) as i32, self.image.height()-1);
+        self.image.get(i, j)
+    }
+}
+
+///The procedural patterns a NoiseTexture can produce, each mapped to [0, 1].
+#[derive(Copy, Clone)]
+pub enum NoisePattern{
+    ///Plain Perlin noise.
+    Perlin,
+    ///Fractal Brownian motion: octaves of Perlin noise.
+    Fbm{octaves: u32},
+    ///Octaves of absolute-valued Perlin noise.
+    Turbulence{octaves: u32},
+    ///Sine stripes along z, distorted by turbulence.
+    Marble{octaves: u32},
+    ///Concentric rings around the y axis, distorted by turbulence.
+    Wood{octaves: u32},
+    ///Distance to the nearest Worley feature point, giving a cellular look.
+    Worley
+}
+
+///A procedural texture blending between two colors by a noise pattern evaluated at scale * p.
+pub struct NoiseTexture{
+    perlin: Perlin,
+    worley: Worley,
+    pattern: NoisePattern,
+    scale: f64,
+    low: Vec3,
+    high: Vec3
+}
+impl NoiseTexture{
+    pub fn new(pattern: NoisePattern, scale: f64, low: Vec3, high: Vec3, seed: u64) -> NoiseTexture{
+        NoiseTexture{perlin: Perlin::new(seed), worley: Worley::new(seed), pattern, scale, low, high}
+    }
+    fn pattern_value(&self, p: &Vec3) -> f64{
+        let q = *p*self.scale;
+        match self.pattern{
+            NoisePattern::Perlin => 0.5*(1.0+self.perlin.noise(&q)),
+            NoisePattern::Fbm{octaves} => 0.5*(1.0+self.perlin.fbm(&q, octaves)),
+            NoisePattern::Turbulence{octaves} => self.perlin.turbulence(&q, octaves),
+            NoisePattern::Marble{octaves} => 0.5*(1.0+f64::sin(q.z + 10.0*self.perlin.turbulence(p, octaves))),
+            NoisePattern::Wood{octaves} => {
+                let rings = f64::sqrt(q.x*q.x + q.z*q.z) + 2.0*self.perlin.turbulence(p, octaves);
+                rings - f64::floor(rings)
+            }
+            NoisePattern::Worley => self.worley.distance(&q)
+        }
+    }
+}
+impl Texture for NoiseTexture{
+    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Vec3{
+        let t = Interval::new(0.0, 1.0).clamp(self.pattern_value(p));
+        self.low*(1.0-t) + self.high*t
+    }
+}
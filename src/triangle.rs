@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::{
+    aabb::Aabb,
+    bvh::BvhNode,
+    hittable::HitRecord,
+    material::Material,
+    quad::area_pdf,
+    sampler::Sampler,
+    utils::{Interval, NEAR_NON_NEG},
+    vec3::{cross, dot, unit_vector},
+    Hittable, Ray, Vec3
+};
+
+///Intersects a ray with the triangle (p0, p1, p2) using the Möller–Trumbore algorithm.
+///Returns the ray parameter and the barycentric weights of p1 and p2 at the hit point.
+fn intersect_triangle(r: &Ray, ray_t: &Interval, p0: Vec3, p1: Vec3, p2: Vec3) -> Option<(f64, f64, f64)>{
+    let edge1 = p1-p0;
+    let edge2 = p2-p0;
+    let h = cross(&r.direction, &edge2);
+    let det = dot(&edge1, &h);
+    if f64::abs(det) < 1e-12{
+        return None;
+    }
+
+    let inverse_det = 1.0/det;
+    let s = r.origin-p0;
+    let b1 = dot(&s, &h)*inverse_det;
+    if !(0.0..=1.0).contains(&b1){
+        return None;
+    }
+    let q = cross(&s, &edge1);
+    let b2 = dot(&r.direction, &q)*inverse_det;
+    if b2 < 0.0 || b1+b2 > 1.0{
+        return None;
+    }
+
+    let t = dot(&edge2, &q)*inverse_det;
+    if !ray_t.surrounds(t){
+        return None;
+    }
+    Some((t, b1, b2))
+}
+///Returns the density over solid angle of direction from origin when points are picked uniformly on a surface
+///of the given area that contains the triangle (p0, p1, p2), counting only where direction meets the triangle.
+fn triangle_pdf(origin: &Vec3, direction: &Vec3, [p0, p1, p2]: [Vec3; 3], area: f64) -> f64{
+    match intersect_triangle(&Ray::new(*origin, *direction, 0.0), &NEAR_NON_NEG, p0, p1, p2){
+        Some((t, _, _)) => area_pdf(t, direction, &unit_vector(&cross(&(p1-p0), &(p2-p0))), area),
+        None => 0.0
+    }
+}
+///Returns a direction from origin toward a uniformly random point on the triangle (p0, p1, p2).
+fn sample_triangle(origin: &Vec3, rng: &mut Sampler, [p0, p1, p2]: [Vec3; 3]) -> Vec3{
+    //Folding the unit square onto its lower half gives uniform barycentric coordinates.
+    let (mut b1, mut b2) = (rng.gen::<f64>(), rng.gen::<f64>());
+    if b1+b2 > 1.0{
+        (b1, b2) = (1.0-b1, 1.0-b2);
+    }
+    p0 + (p1-p0)*b1 + (p2-p0)*b2 - *origin
+}
+fn triangle_area([p0, p1, p2]: [Vec3; 3]) -> f64{
+    0.5*cross(&(p1-p0), &(p2-p0)).length()
+}
+
+///A single flat-shaded triangle; its outward normal follows the counter-clockwise winding of its vertices.
+///Its (u, v) coordinates are the barycentric weights of the second and third vertices.
+pub struct Triangle{
+    vertices: [Vec3; 3],
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+impl Triangle{
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, material: Arc<dyn Material>) -> Triangle{
+        let bbox = Aabb::surrounding(&Aabb::from_points(p0, p1), &Aabb::from_points(p2, p2)).padded();
+        Triangle{vertices: [p0, p1, p2], material, bbox}
+    }
+}
+impl Hittable for Triangle{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let [p0, p1, p2] = self.vertices;
+        let (t, b1, b2) = intersect_triangle(r, ray_t, p0, p1, p2)?;
+        let normal = unit_vector(&cross(&(p1-p0), &(p2-p0)));
+        Some(HitRecord::generate(r.at(t), normal, t, b1, b2, r, self.material.clone()))
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        triangle_pdf(origin, direction, self.vertices, triangle_area(self.vertices))
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        sample_triangle(origin, rng, self.vertices)
+    }
+    fn is_emitter(&self) -> bool{
+        self.material.is_emissive()
+    }
+}
+
+///Vertex buffers shared by every triangle of a mesh. Each vertex has a position and, optionally,
+///a normal and a texture coordinate; normals and uvs are either empty or as long as positions.
+pub struct MeshData{
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[f64; 2]>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>
+}
+impl MeshData{
+    fn face_positions(&self, face: usize) -> [Vec3; 3]{
+        self.indices[face].map(|i| self.positions[i as usize])
+    }
+}
+
+///One face of a mesh, referring to its vertices through the shared buffers.
+struct MeshTriangle{
+    mesh: Arc<MeshData>,
+    face: usize
+}
+impl MeshTriangle{
+    fn vertex(&self, corner: usize) -> usize{
+        self.mesh.indices[self.face][corner] as usize
+    }
+}
+impl Hittable for MeshTriangle{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        let mesh = &self.mesh;
+        let (i0, i1, i2) = (self.vertex(0), self.vertex(1), self.vertex(2));
+        let (p0, p1, p2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+        let (t, b1, b2) = intersect_triangle(r, ray_t, p0, p1, p2)?;
+
+        //Which side was hit is decided by the true surface; the smooth normal is only used for shading.
+        let geometric_normal = cross(&(p1-p0), &(p2-p0));
+        let front_face = dot(&r.direction, &geometric_normal) < 0.0;
+        let outward_normal = if mesh.normals.is_empty(){
+            unit_vector(&geometric_normal)
+        }else{
+            let n = mesh.normals[i0]*(1.0-b1-b2) + mesh.normals[i1]*b1 + mesh.normals[i2]*b2;
+            let n = if n.near_zero() {geometric_normal} else {n};
+            unit_vector(&(if dot(&n, &geometric_normal) < 0.0 {-n} else {n}))
+        };
+        let normal = if front_face {outward_normal} else {-outward_normal};
+
+        //Without texture coordinates, the barycentric weights stand in for (u, v).
+        let (u, v) = if mesh.uvs.is_empty(){
+            (b1, b2)
+        }else{
+            let w0 = 1.0-b1-b2;
+            let [u0, v0] = mesh.uvs[i0];
+            let [u1, v1] = mesh.uvs[i1];
+            let [u2, v2] = mesh.uvs[i2];
+            (u0*w0 + u1*b1 + u2*b2, v0*w0 + v1*b1 + v2*b2)
+        };
+
+        Some(HitRecord::new(r.at(t), normal, t, u, v, front_face, mesh.material.clone()))
+    }
+    fn bounding_box(&self) -> Aabb{
+        let p = |corner| self.mesh.positions[self.vertex(corner)];
+        Aabb::surrounding(&Aabb::from_points(p(0), p(1)), &Aabb::from_points(p(2), p(2))).padded()
+    }
+}
+
+///An indexed triangle mesh whose faces share one set of vertex buffers and one material,
+///with its own bounding volume hierarchy over the faces.
+///Emissive meshes are sampled uniformly over their area, which tests the direction against every face.
+pub struct TriangleMesh{
+    bvh: BvhNode,
+    mesh: Arc<MeshData>,
+    ///The total area of the faces up to and including each one.
+    cumulative_areas: Vec<f64>
+}
+impl TriangleMesh{
+    pub fn new(mesh: MeshData) -> TriangleMesh{
+        let mesh = Arc::new(mesh);
+        let faces: Vec<Box<dyn Hittable>> = (0..mesh.indices.len())
+            .map(|face| Box::new(MeshTriangle{mesh: mesh.clone(), face}) as Box<dyn Hittable>)
+            .collect();
+        let cumulative_areas = (0..mesh.indices.len())
+            .scan(0.0, |total, face| {
+                *total += triangle_area(mesh.face_positions(face));
+                Some(*total)
+            })
+            .collect();
+        TriangleMesh{bvh: BvhNode::new(faces), mesh, cumulative_areas}
+    }
+    fn area(&self) -> f64{
+        self.cumulative_areas.last().copied().unwrap_or(0.0)
+    }
+}
+impl Hittable for TriangleMesh{
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>{
+        self.bvh.intersect(r, ray_t)
+    }
+    fn bounding_box(&self) -> Aabb{
+        self.bvh.bounding_box()
+    }
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64{
+        if !self.bvh.bounding_box().hit(&Ray::new(*origin, *direction, 0.0), &NEAR_NON_NEG){
+            return 0.0;
+        }
+        (0..self.mesh.indices.len())
+            .map(|face| triangle_pdf(origin, direction, self.mesh.face_positions(face), self.area()))
+            .sum()
+    }
+    fn random(&self, origin: &Vec3, rng: &mut Sampler) -> Vec3{
+        let target = rng.gen::<f64>()*self.area();
+        let face = self.cumulative_areas.partition_point(|&total| total <= target).min(self.cumulative_areas.len()-1);
+        sample_triangle(origin, rng, self.mesh.face_positions(face))
+    }
+    fn is_emitter(&self) -> bool{
+        self.mesh.material.is_emissive() && self.area() > 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{material::{DiffuseLight, Lambertian}, pdf::tests::check_sampling};
+    use super::*;
+
+    fn mesh(material: Arc<dyn Material>) -> TriangleMesh{
+        //Two faces of different sizes at an angle to each other.
+        TriangleMesh::new(MeshData{
+            positions: vec![
+                Vec3::new(-1.0, -1.0, 2.0), Vec3::new(1.0, -1.0, 2.0), Vec3::new(-1.0, 1.0, 2.0),
+                Vec3::new(1.0, 0.0, 1.0), Vec3::new(2.0, 0.0, 2.5)
+            ],
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: vec![[0, 1, 2], [1, 3, 4]],
+            material
+        })
+    }
+
+    #[test]
+    fn samples_emissive_meshes_by_area(){
+        let mesh = mesh(Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))));
+        assert!(mesh.is_emitter());
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let total = check_sampling(|d| mesh.pdf_value(&origin, d), |rng| mesh.random(&origin, rng));
+        //The edges of the faces cut through bins, which the midpoint rule only approximates.
+        assert!((total-1.0).abs() < 1e-2, "{}", total);
+    }
+
+    #[test]
+    fn only_emissive_meshes_are_emitters(){
+        assert!(!mesh(Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))).is_emitter());
+    }
+}
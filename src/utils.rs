@@ -3,6 +3,7 @@ pub const PI: f64 = std::f64::consts::PI;
 pub fn degrees_to_radians(degrees: f64) -> f64{
     degrees/180.0*PI
 }
+#[derive(Copy, Clone)]
 pub struct Interval{
     pub min: f64,
     pub max: f64
@@ -20,6 +21,10 @@ impl Interval{
     pub fn size(&self) -> f64{
         self.max - self.min
     }
+    ///Returns the smallest interval containing both intervals.
+    pub fn enclosing(a: &Interval, b: &Interval) -> Interval{
+        Interval{min: f64::min(a.min, b.min), max: f64::max(a.max, b.max)}
+    }
     pub fn clamp(&self, x: f64) -> f64{
         if x < self.min {return self.min};
         if x > self.max {return self.max};
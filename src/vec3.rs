@@ -94,13 +94,11 @@ pub fn cross(lhs: &Vec3, rhs: &Vec3) -> Vec3{
 }
 
 ///Returns a random vector with values between 0 and 1.
-pub fn random_vec() -> Vec3{
-    let mut rng = rand::thread_rng();
+pub fn random_vec(rng: &mut impl Rng) -> Vec3{
     Vec3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())
 }
 ///Returns a random vector with values between min and max.
-pub fn random_vec_range(min: f64, max: f64) -> Vec3{
-    let mut rng = rand::thread_rng();
+pub fn random_vec_range(min: f64, max: f64, rng: &mut impl Rng) -> Vec3{
     Vec3::new(rng.gen_range(min..max), rng.gen_range(min..max), rng.gen_range(min..max))
 }
 ///Returns a normalized vector from vec.
@@ -108,33 +106,38 @@ pub fn unit_vector(vec: &Vec3) -> Vec3{
     Vec3::new(vec.x, vec.y, vec.z)/(vec.length())
 }
 ///Returns a random unit vector by generating and normalizing a 3d vector from a Gaussian distribution.
-pub fn random_unit_vector() -> Vec3{
-    let mut rng = rand::thread_rng();
+pub fn random_unit_vector(rng: &mut impl Rng) -> Vec3{
     let normal = Normal::new(0.0, 1.0).unwrap();
     let v = Vec3::new(
-        normal.sample(&mut rng),
-        normal.sample(&mut rng),
-        normal.sample(&mut rng)
+        normal.sample(rng),
+        normal.sample(rng),
+        normal.sample(rng)
     );
     return unit_vector(&v);
 }
 ///Returns a random unit vector on the hemisphere defined by the normal n.
-pub fn random_on_hemisphere(normal: &Vec3) -> Vec3{
-    let v = random_unit_vector();
+pub fn random_on_hemisphere(normal: &Vec3, rng: &mut impl Rng) -> Vec3{
+    let v = random_unit_vector(rng);
     if dot(normal, &v) > 0.0{
         return v;
     }else{
         return -v;
     }
 }
-pub fn random_in_unit_disk() -> Vec3{
-    let rho: f64 = f64::sqrt(rand::random::<f64>());
-    let phi: f64 = 2.0*PI*rand::random::<f64>();
+pub fn random_in_unit_disk(rng: &mut impl Rng) -> Vec3{
+    let rho: f64 = f64::sqrt(rng.gen::<f64>());
+    let phi: f64 = 2.0*PI*rng.gen::<f64>();
 
     let x = rho * f64::cos(phi);
     let y = rho * f64::sin(phi);
     return Vec3::new(x, y, 0.0);
 }
+///Returns a random direction on the +z hemisphere, with density proportional to the cosine from +z.
+pub fn random_cosine_direction(rng: &mut impl Rng) -> Vec3{
+    let p = random_in_unit_disk(rng);
+    let z = f64::sqrt(f64::max(1.0-p.x*p.x-p.y*p.y, 0.0));
+    return Vec3::new(p.x, p.y, z);
+}
 
 ///Reflects a vector v off the surface defined by the normal n.
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3{